@@ -0,0 +1,75 @@
+//! Throughput of hiding and revealing messages with each steganography method and bit distribution.
+//!
+//! Run with `cargo bench`, criterion reports the throughput in MB/s of message hidden or revealed.
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use image::RgbImage;
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg64;
+
+use stegosaurust::steganography::{
+    linear_length, max_len, BitDistribution, BitEncoder, BitEncoding, Lsb, Rsb, Steganography,
+};
+
+/// Width and height of the cover image
+const SIZE: u32 = 1000;
+
+fn cover() -> RgbImage {
+    let mut rng = Pcg64::seed_from_u64(0);
+    RgbImage::from_fn(SIZE, SIZE, |_, _| image::Rgb(rng.gen()))
+}
+
+fn bit_encoding(method: &str) -> Box<dyn BitEncoding> {
+    match method {
+        "rsb" => Box::new(Rsb::new(2, "bench").unwrap()),
+        _ => Box::<Lsb>::default(),
+    }
+}
+
+fn distributions(msg_len: usize) -> [(&'static str, BitDistribution); 2] {
+    [
+        ("sequential", BitDistribution::Sequential),
+        (
+            "linear",
+            BitDistribution::Linear {
+                length: linear_length(msg_len),
+            },
+        ),
+    ]
+}
+
+fn bench_steganography(c: &mut Criterion) {
+    let img = cover();
+    let msg_len = max_len(SIZE, SIZE);
+    let mut rng = Pcg64::seed_from_u64(1);
+    let msg = (0..msg_len).map(|_| rng.gen()).collect::<Vec<u8>>();
+
+    let mut group = c.benchmark_group("steganography");
+    group.throughput(Throughput::Bytes(msg_len as u64));
+    group.sample_size(10);
+    for method in ["lsb", "rsb"] {
+        for (name, distribution) in distributions(msg_len) {
+            let encoded = BitEncoder::new(bit_encoding(method), Some(distribution.clone()))
+                .encode(&img, &msg)
+                .unwrap();
+
+            group.bench_function(BenchmarkId::new(format!("encode/{}", method), name), |b| {
+                b.iter(|| {
+                    BitEncoder::new(bit_encoding(method), Some(distribution.clone()))
+                        .encode(&img, &msg)
+                        .unwrap()
+                })
+            });
+            group.bench_function(BenchmarkId::new(format!("decode/{}", method), name), |b| {
+                b.iter(|| {
+                    BitEncoder::new(bit_encoding(method), Some(distribution.clone()))
+                        .decode(&encoded)
+                        .unwrap()
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_steganography);
+criterion_main!(benches);
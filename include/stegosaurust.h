@@ -0,0 +1,137 @@
+#ifndef STEGOSAURUST_H
+#define STEGOSAURUST_H
+
+/* Generated with cbindgen from src/ffi.rs, regenerate with `just header` rather than editing it. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// Hide bits in the least significant bit of each colour value
+#define STEG_METHOD_LSB 0
+
+// Hide bits in a random bit, up to `max_bit`, of each colour value
+#define STEG_METHOD_RSB 1
+
+// Hide bits in consecutive colour values
+#define STEG_DISTRIBUTION_SEQUENTIAL 0
+
+// Spread bits evenly across the image
+#define STEG_DISTRIBUTION_LINEAR 1
+
+// Outcome of a call, mapped from the errors of the library.
+//
+// Values are stable: new ones are only ever added.
+typedef enum StegStatus {
+  STEG_STATUS_OK = 0,
+  // A required pointer was null
+  STEG_STATUS_NULL_POINTER = 1,
+  // A string was not valid UTF-8
+  STEG_STATUS_INVALID_UTF8 = 2,
+  // The options were not valid, e.g. rsb without a seed
+  STEG_STATUS_INVALID_CONFIG = 3,
+  // The payload did not fit in the image
+  STEG_STATUS_ENCODING = 10,
+  // No hidden data was found in the image
+  STEG_STATUS_ENCODING_NOT_FOUND = 11,
+  STEG_STATUS_DECODING = 12,
+  STEG_STATUS_VERIFICATION = 13,
+  STEG_STATUS_BASE64 = 14,
+  STEG_STATUS_COMPRESSION = 20,
+  STEG_STATUS_DECOMPRESSION = 21,
+  STEG_STATUS_COMPRESSION_EMPTY_DATA = 22,
+  STEG_STATUS_CRYPTO_SALT = 30,
+  STEG_STATUS_CRYPTO_PASSWORD_HASH = 31,
+  STEG_STATUS_CRYPTO_CIPHER = 32,
+  // Decrypting failed, most likely with the wrong key
+  STEG_STATUS_CRYPTO_DECRYPTION = 33,
+  STEG_STATUS_CRYPTO_UNKNOWN = 34,
+  // The library panicked, which is a bug
+  STEG_STATUS_PANIC = 98,
+  STEG_STATUS_UNKNOWN = 99,
+} StegStatus;
+
+// How to hide and reveal a payload, as the options of `stegosaurust encode` and `decode`
+typedef struct StegOptions {
+  // `STEG_METHOD_LSB` or `STEG_METHOD_RSB`
+  uint32_t method;
+  // `STEG_DISTRIBUTION_SEQUENTIAL` or `STEG_DISTRIBUTION_LINEAR`
+  uint32_t distribution;
+  // Length of a linear distribution, needed to decode, from `stegosaurust encode` or `steg_linear_length`
+  size_t linear_length;
+  // Seed for random significant bit encoding, may be null otherwise
+  const char *seed;
+  // Maximum bit (1-4) to hide bits in with random significant bit encoding
+  uint8_t max_bit;
+  // Compress the payload
+  bool compress;
+  // Key to encrypt the payload with using AES-256-CBC, null not to encrypt it
+  const char *key;
+  // Base64 encode the payload
+  bool base64;
+} StegOptions;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Options hiding payloads in the least significant bits, sequentially, without transforming them
+struct StegOptions steg_options_default(void);
+
+// Static, null terminated description of a status, or of an unknown status if `status` is not a `StegStatus`
+const char *steg_status_message(int status);
+
+// Maximum length of a payload, once transformed, that can be hidden in an image of `width` by `height` pixels
+size_t steg_capacity(uint32_t width,
+                     uint32_t height);
+
+// Length of the linear distribution a payload hidden with `STEG_DISTRIBUTION_LINEAR` is spread over,
+// from the length of the payload once transformed
+size_t steg_linear_length(size_t encoded_len);
+
+// Hide a payload in the raw buffer of an RGB image, with three colour values to a pixel, in place.
+//
+// On success `*encoded_len`, unless null, is the length of the payload once transformed, as hidden in the image,
+// to pass to `steg_linear_length` when it was hidden with `STEG_DISTRIBUTION_LINEAR`.
+//
+// # Safety
+//
+// `options` must point to valid options, with null or null terminated strings, `pixels` to `pixels_len` bytes,
+// `payload` to `payload_len` bytes, or be null if `payload_len` is 0, and `encoded_len` to a writable location
+// or be null.
+enum StegStatus steg_encode(const struct StegOptions *options,
+                            uint8_t *pixels,
+                            size_t pixels_len,
+                            const uint8_t *payload,
+                            size_t payload_len,
+                            size_t *encoded_len);
+
+// Reveal the payload hidden in the raw buffer of an RGB image, with three colour values to a pixel.
+//
+// On success `*payload` points to `*payload_len` bytes, to free with `steg_bytes_free`.
+//
+// # Safety
+//
+// `options` must point to valid options, with null or null terminated strings, `pixels` to `pixels_len` bytes,
+// and `payload` and `payload_len` to writable locations.
+enum StegStatus steg_decode(const struct StegOptions *options,
+                            const uint8_t *pixels,
+                            size_t pixels_len,
+                            uint8_t **payload,
+                            size_t *payload_len);
+
+// Free a payload returned by `steg_decode`.
+//
+// # Safety
+//
+// `payload` and `payload_len` must have been returned by `steg_decode` and not freed already, or `payload` be null.
+void steg_bytes_free(uint8_t *payload,
+                     size_t payload_len);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* STEGOSAURUST_H */
@@ -0,0 +1,90 @@
+import numpy as np
+import pytest
+
+import stegosaurust
+
+
+@pytest.fixture
+def cover():
+    rng = np.random.default_rng(0)
+    return rng.integers(0, 256, size=(64, 48, 3), dtype=np.uint8)
+
+
+@pytest.mark.parametrize(
+    "method, distribution",
+    [
+        (stegosaurust.Lsb, "sequential"),
+        (stegosaurust.Lsb, "linear"),
+        (lambda distribution: stegosaurust.Rsb("seed", 2, distribution), "sequential"),
+        (lambda distribution: stegosaurust.Rsb("seed", 4, distribution), "linear"),
+    ],
+)
+def test_encode_decode(cover, method, distribution):
+    message = b"hello world"
+    image = method(distribution).encode(cover, message)
+    assert image.shape == cover.shape
+    assert image.dtype == np.uint8
+    assert not np.array_equal(image, cover)
+    assert np.abs(image.astype(int) - cover.astype(int)).max() < 16
+    # a linear distribution is revealed knowing the length it was spread over
+    if distribution == "linear":
+        distribution = f"linear-{stegosaurust.linear_length(len(message))}"
+    assert method(distribution).decode(image) == message
+
+
+def test_decode_linear_needs_length(cover):
+    image = stegosaurust.Lsb("linear").encode(cover, b"hello world")
+    length = stegosaurust.linear_length(len(b"hello world"))
+    assert stegosaurust.Lsb(f"linear-{length}").decode(image) == b"hello world"
+
+
+def test_max_len(cover):
+    assert stegosaurust.Lsb().max_len(cover) == stegosaurust.capacity(48, 64)
+    with pytest.raises(stegosaurust.StegError) as err:
+        stegosaurust.Lsb().encode(cover, bytes(stegosaurust.capacity(48, 64) + 1))
+    assert err.value.args[1] == "STEG_ENCODING"
+
+
+def test_non_contiguous_image(cover):
+    # a view of every other column, as slicing in a notebook would give
+    view = cover[:, ::2]
+    image = stegosaurust.Lsb().encode(view, b"hello")
+    assert stegosaurust.Lsb().decode(image) == b"hello"
+
+
+def test_invalid_image_and_options(cover):
+    with pytest.raises(ValueError):
+        stegosaurust.Lsb().encode(cover[:, :, :2], b"hello")
+    with pytest.raises(stegosaurust.StegError) as err:
+        stegosaurust.Rsb("seed", 5)
+    assert err.value.args[1] == "STEG_INVALID_CONFIG"
+    with pytest.raises(stegosaurust.StegError):
+        stegosaurust.Lsb("diagonal")
+    with pytest.raises(stegosaurust.StegError) as err:
+        stegosaurust.Lsb().decode(np.zeros((8, 8, 3), dtype=np.uint8))
+    assert err.value.args[1] == "STEG_ENCODING_NOT_FOUND"
+
+
+def test_compress_and_crypto(cover):
+    message = b"hello world " * 10
+    packed = stegosaurust.encrypt(stegosaurust.compress(message), b"secret")
+    assert len(packed) == stegosaurust.encrypted_len(len(stegosaurust.compress(message)))
+    image = stegosaurust.Rsb("seed", 2).encode(cover, packed)
+    revealed = stegosaurust.Rsb("seed", 2).decode(image)
+    assert stegosaurust.decompress(stegosaurust.decrypt(revealed, b"secret")) == message
+    with pytest.raises(stegosaurust.CryptoError) as err:
+        stegosaurust.decrypt(revealed, b"wrong")
+    assert err.value.args[1] == "CRYPTO_DECRYPTION"
+    with pytest.raises(stegosaurust.CompressionError):
+        stegosaurust.decompress(b"not bzip2")
+
+
+def test_analysis():
+    assert stegosaurust.capacity(32, 32) == 380
+    width = stegosaurust.min_square_width(1000)
+    assert stegosaurust.capacity(width, width) >= 1000
+    assert stegosaurust.base64_len(4) == 8
+    assert stegosaurust.embedding_rate(0, 8, 8) == 4 * 8 / (8 * 8 * 3)
+    assert ("rsb", 4, "linear") in stegosaurust.combinations()
+    assert stegosaurust.detectability("lsb", 1, "linear", 10, 1000, 1000) == "low"
+    assert stegosaurust.detectability("lsb", 1, "sequential", 10**6, 10, 10) is None
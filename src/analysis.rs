@@ -0,0 +1,187 @@
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::steganography;
+use crate::steganography::{BitDistribution, StegMethod};
+
+/// How likely it is that hiding a message is noticed by steganalysis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Detectability {
+    Low,
+    Medium,
+    High,
+}
+
+impl fmt::Display for Detectability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Detectability::Low => write!(f, "low"),
+            Detectability::Medium => write!(f, "medium"),
+            Detectability::High => write!(f, "high"),
+        }
+    }
+}
+
+/// Every combination of encoding method, maximum bit and bit distribution that can be used to hide a message.
+pub fn combinations() -> Vec<(StegMethod, u8, BitDistribution)> {
+    let methods = std::iter::once((StegMethod::LeastSignificantBit, 1))
+        .chain((1..=4).map(|n| (StegMethod::RandomSignificantBit, n)));
+    methods
+        .flat_map(|(method, max_bit)| {
+            [
+                BitDistribution::Sequential,
+                BitDistribution::Linear { length: 0 },
+            ]
+            .into_iter()
+            .map(move |dist| (method, max_bit, dist))
+        })
+        .collect()
+}
+
+/// Number of bytes that can be hidden in an image of the given dimensions.
+///
+/// # Example
+/// ```rust
+/// use stegosaurust::analysis::capacity;
+/// assert_eq!(capacity(32, 32), 380);
+/// ```
+pub fn capacity(width: u32, height: u32) -> usize {
+    steganography::max_len(width, height)
+}
+
+/// Smallest width of a square image that can hide `len` bytes.
+///
+/// This is the theoretical minimum, `disguise` requests cover images about twice as wide.
+///
+/// # Example
+/// ```rust
+/// use stegosaurust::analysis::{capacity, min_square_width};
+/// let width = min_square_width(1000);
+/// assert!(capacity(width, width) >= 1000);
+/// assert!(capacity(width - 1, width - 1) < 1000);
+/// ```
+pub fn min_square_width(len: usize) -> u32 {
+    let mut width = (((len * 8) as f64 / 3.).sqrt() as u32).max(1);
+    while capacity(width, width) < len {
+        width += 1;
+    }
+    width
+}
+
+/// Length of a message once encrypted with `crypto::encrypt`, including the header, salt and padding.
+pub fn encrypted_len(len: usize) -> usize {
+    16 + (len / 16 + 1) * 16
+}
+
+/// Length of a message once base64 encoded.
+pub fn base64_len(len: usize) -> usize {
+    len.div_ceil(3) * 4
+}
+
+/// Fraction of the colour values of an image of the given dimensions used to hide `len` bytes.
+pub fn embedding_rate(len: usize, width: u32, height: u32) -> f64 {
+    let channels = width as f64 * height as f64 * 3.;
+    if channels == 0. {
+        return f64::INFINITY;
+    }
+    ((len + steganography::END.len()) * 8) as f64 / channels
+}
+
+/// Estimate how detectable hiding `len` bytes in an image of the given dimensions is.
+///
+/// The estimate grows with the embedding rate and with the average change made to each colour value,
+/// which is larger when bits can be hidden in more significant bits.
+/// Sequential distribution packs every change into the top of the image, making it more detectable than
+/// spreading the changes out linearly.
+///
+/// Returns `None` if the message does not fit in the image.
+pub fn detectability(
+    method: StegMethod,
+    max_bit: u8,
+    distribution: &BitDistribution,
+    len: usize,
+    width: u32,
+    height: u32,
+) -> Option<Detectability> {
+    if len > capacity(width, height) {
+        return None;
+    }
+    // mean change in value when the chosen bit is flipped
+    let magnitude = match method {
+        StegMethod::LeastSignificantBit => 1.,
+        StegMethod::RandomSignificantBit => {
+            let n = max_bit.clamp(1, 4) as i32;
+            (2f64.powi(n) - 1.) / n as f64
+        }
+    };
+    let concentration = match distribution {
+        BitDistribution::Sequential => 1.5,
+        BitDistribution::Linear { .. } => 1.,
+    };
+    let score = embedding_rate(len, width, height) * magnitude * concentration;
+    Some(if score < 0.1 {
+        Detectability::Low
+    } else if score < 0.4 {
+        Detectability::Medium
+    } else {
+        Detectability::High
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto;
+    use crate::steganography::{BitEncoder, Lsb, Steganography};
+    use image::RgbImage;
+
+    #[test]
+    fn test_capacity_matches_encoder() {
+        let img = RgbImage::new(40, 25);
+        let enc = BitEncoder::new(Box::<Lsb>::default(), None);
+        assert_eq!(capacity(40, 25), enc.max_len(&img));
+    }
+
+    #[test]
+    fn test_encrypted_len() {
+        for len in [0, 1, 15, 16, 17, 100] {
+            let ciphertext = crypto::encrypt(&vec![0; len], b"key").unwrap();
+            assert_eq!(encrypted_len(len), ciphertext.len());
+        }
+    }
+
+    #[test]
+    fn test_base64_len() {
+        for len in [0, 1, 2, 3, 4, 100] {
+            assert_eq!(base64_len(len), base64::encode(vec![0; len]).len());
+        }
+    }
+
+    #[test]
+    fn test_detectability() {
+        let lsb = StegMethod::LeastSignificantBit;
+        let rsb = StegMethod::RandomSignificantBit;
+        let linear = BitDistribution::Linear { length: 0 };
+        let seq = BitDistribution::Sequential;
+        assert_eq!(
+            detectability(lsb, 1, &linear, 10, 500, 500),
+            Some(Detectability::Low)
+        );
+        assert_eq!(
+            detectability(rsb, 4, &seq, capacity(100, 100), 100, 100),
+            Some(Detectability::High)
+        );
+        assert!(
+            detectability(lsb, 1, &seq, 5000, 100, 100)
+                >= detectability(lsb, 1, &linear, 5000, 100, 100)
+        );
+        assert_eq!(detectability(lsb, 1, &seq, 10_000, 10, 10), None);
+    }
+
+    #[test]
+    fn test_combinations() {
+        assert_eq!(combinations().len(), 10);
+    }
+}
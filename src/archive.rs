@@ -0,0 +1,262 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+
+use walkdir::WalkDir;
+
+use crate::payload::{write_bytes, write_varint, Reader};
+use crate::StegError;
+
+/// A file stored in an `Archive`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// Path of the file relative to the root of the archive, separated by `/`
+    pub path: String,
+    /// Unix permission bits of the file, without the setuid, setgid and sticky bits
+    pub mode: Option<u32>,
+    /// Contents of the file
+    pub data: Vec<u8>,
+}
+
+impl Entry {
+    /// Path to extract the entry to relative to a target directory.
+    ///
+    /// Returns `None` for paths that could escape the target directory, such as absolute paths or those containing `..`.
+    pub fn relative_path(&self) -> Option<PathBuf> {
+        let path = Path::new(&self.path);
+        if path.as_os_str().is_empty()
+            || !path.components().all(|c| matches!(c, Component::Normal(_)))
+        {
+            return None;
+        }
+        Some(path.to_path_buf())
+    }
+
+    /// Extract the entry into `dir`, creating any parent directories, returning the path written.
+    ///
+    /// Existing files are never overwritten, and files are never extracted setuid or setgid.
+    pub fn extract(&self, dir: &Path) -> std::io::Result<PathBuf> {
+        let relative = self.relative_path().ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("unsafe path in archive: {}", self.path),
+            )
+        })?;
+        let path = dir.join(relative);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut f = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        f.write_all(&self.data)?;
+        #[cfg(unix)]
+        if let Some(mode) = self.mode {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode & 0o777))?;
+        }
+        Ok(path)
+    }
+
+    /// Whether the entry is the file selected, or is inside the directory selected
+    fn is_selected_by(&self, selected: &str) -> bool {
+        let selected = selected.trim_end_matches('/');
+        self.path == selected
+            || self
+                .path
+                .strip_prefix(selected)
+                .map(|rest| rest.starts_with('/'))
+                .unwrap_or(false)
+    }
+}
+
+/// A compact archive of files, keeping their paths, sizes and modes so they can be hidden in a single image.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Archive {
+    pub entries: Vec<Entry>,
+}
+
+impl Archive {
+    /// Create an archive from files and directories.
+    ///
+    /// Files are stored under their name and directories are walked recursively, with entries stored under the directory's name.
+    pub fn from_paths(paths: &[PathBuf]) -> std::io::Result<Self> {
+        let mut entries = Vec::new();
+        for path in paths {
+            let root = path.parent().unwrap_or_else(|| Path::new(""));
+            for dirent in WalkDir::new(path).sort_by_file_name() {
+                let dirent = dirent?;
+                if !dirent.file_type().is_file() {
+                    continue;
+                }
+                let relative = dirent.path().strip_prefix(root).unwrap_or(dirent.path());
+                let relative = relative
+                    .components()
+                    .filter_map(|c| match c {
+                        Component::Normal(part) => Some(part.to_string_lossy()),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>()
+                    .join("/");
+                entries.push(Entry {
+                    path: relative,
+                    mode: file_mode(dirent.path())?,
+                    data: std::fs::read(dirent.path())?,
+                });
+            }
+        }
+        Ok(Archive { entries })
+    }
+
+    /// Total size of all files in the archive
+    pub fn size(&self) -> usize {
+        self.entries.iter().map(|e| e.data.len()).sum()
+    }
+
+    /// Select the entries matching the given paths, or all entries if none are given.
+    ///
+    /// A path selects the file with that path, or every file inside the directory with that path.
+    pub fn select(&self, paths: &[String]) -> Result<Vec<&Entry>, StegError> {
+        if paths.is_empty() {
+            return Ok(self.entries.iter().collect());
+        }
+        if let Some(missing) = paths
+            .iter()
+            .find(|p| !self.entries.iter().any(|e| e.is_selected_by(p)))
+        {
+            return Err(StegError::Decoding(format!(
+                "no entry {} in archive",
+                missing
+            )));
+        }
+        Ok(self
+            .entries
+            .iter()
+            .filter(|e| paths.iter().any(|p| e.is_selected_by(p)))
+            .collect())
+    }
+
+    pub(crate) fn write(&self, out: &mut Vec<u8>) {
+        write_varint(out, self.entries.len() as u64);
+        for entry in &self.entries {
+            write_bytes(out, entry.path.as_bytes());
+            // modes are stored offset by one so zero means no mode was recorded
+            write_varint(out, entry.mode.map(|m| m as u64 + 1).unwrap_or(0));
+            write_bytes(out, &entry.data);
+        }
+    }
+
+    pub(crate) fn read(reader: &mut Reader) -> Result<Self, StegError> {
+        let count = reader.varint()?;
+        let mut entries = Vec::new();
+        for _ in 0..count {
+            let path = reader.string()?;
+            let mode = match reader.varint()? {
+                0 => None,
+                m => Some((m - 1) as u32),
+            };
+            let data = reader.bytes()?.to_vec();
+            entries.push(Entry { path, mode, data });
+        }
+        Ok(Archive { entries })
+    }
+}
+
+#[cfg(unix)]
+fn file_mode(path: &Path) -> std::io::Result<Option<u32>> {
+    use std::os::unix::fs::PermissionsExt;
+    Ok(Some(std::fs::metadata(path)?.permissions().mode() & 0o777))
+}
+
+#[cfg(not(unix))]
+fn file_mode(_path: &Path) -> std::io::Result<Option<u32>> {
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn archive() -> Archive {
+        Archive {
+            entries: vec![
+                Entry {
+                    path: "docs/a.txt".to_string(),
+                    mode: Some(0o644),
+                    data: b"aaa".to_vec(),
+                },
+                Entry {
+                    path: "docs/sub/b.txt".to_string(),
+                    mode: None,
+                    data: b"bbbb".to_vec(),
+                },
+                Entry {
+                    path: "c.bin".to_string(),
+                    mode: Some(0o755),
+                    data: vec![0, 1, 2],
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_archive_roundtrip() {
+        let mut bytes = Vec::new();
+        archive().write(&mut bytes);
+        let read = Archive::read(&mut Reader::new(&bytes)).unwrap();
+        assert_eq!(read, archive());
+        assert_eq!(read.size(), 10);
+    }
+
+    #[test]
+    fn test_archive_select() {
+        let archive = archive();
+        assert_eq!(archive.select(&[]).unwrap().len(), 3);
+        let selected = archive.select(&["docs".to_string()]).unwrap();
+        assert_eq!(selected.len(), 2);
+        let selected = archive.select(&["docs/sub/".to_string()]).unwrap();
+        assert_eq!(selected[0].path, "docs/sub/b.txt");
+        assert!(archive.select(&["doc".to_string()]).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_modes_drop_setuid_and_setgid() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = std::env::temp_dir().join(format!("stegosaurust-archive-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("run.sh");
+        std::fs::write(&path, b"#!/bin/sh").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o6755)).unwrap();
+        assert_eq!(file_mode(&path).unwrap(), Some(0o755));
+
+        let entry = Entry {
+            path: "out/run.sh".to_string(),
+            mode: Some(0o6755),
+            data: b"#!/bin/sh".to_vec(),
+        };
+        let extracted = entry.extract(&dir).unwrap();
+        let mode = std::fs::metadata(&extracted).unwrap().permissions().mode();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(mode & 0o7777, 0o755);
+    }
+
+    #[test]
+    fn test_unsafe_entry_paths() {
+        let entry = |path: &str| Entry {
+            path: path.to_string(),
+            mode: None,
+            data: Vec::new(),
+        };
+        assert!(entry("../escape").relative_path().is_none());
+        assert!(entry("/etc/passwd").relative_path().is_none());
+        assert!(entry("a/../../b").relative_path().is_none());
+        assert!(entry("").relative_path().is_none());
+        assert_eq!(
+            entry("a/b.txt").relative_path(),
+            Some(PathBuf::from("a/b.txt"))
+        );
+    }
+}
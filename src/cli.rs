@@ -1,14 +1,49 @@
 use std::path::PathBuf;
 use std::str::FromStr;
+use std::time::Duration;
 use structopt::StructOpt;
 
+use crate::image_api::ClientConfig;
+pub use crate::steganography::{BitDistribution, StegMethod};
+use crate::stego::{Stego, StegoBuilder};
+
 #[derive(StructOpt)]
 #[structopt(name = "🦕 stegosaurust", about = "Hide text in images, using rust.")]
 pub struct Opt {
+    /// Output format
+    #[structopt(long, global = true, default_value = "text", possible_values = &Format::variants())]
+    pub format: Format,
+
     #[structopt(subcommand)]
     pub cmd: Command,
 }
 
+/// Supported output formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Human readable text, tables and logs
+    Text,
+    /// Machine readable JSON
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            other => Err(format!("unknown output format: {}", other)),
+        }
+    }
+}
+
+impl Format {
+    fn variants() -> [&'static str; 2] {
+        ["text", "json"]
+    }
+}
+
 #[derive(StructOpt)]
 pub enum Command {
     #[structopt(
@@ -17,12 +52,24 @@ pub enum Command {
         about = "encode files using steganography"
     )]
     Encode(Encode),
+    #[structopt(
+        name = "decode",
+        visible_alias = "dec",
+        about = "decode files hidden using steganography"
+    )]
+    Decode(Decode),
     #[structopt(
         name = "disguise",
         visible_alias = "dsg",
         about = "mask all files in a directory using steganography"
     )]
     Disguise(Disguise),
+    #[structopt(
+        name = "capacity",
+        visible_alias = "cap",
+        about = "plan which settings and cover images can hide a payload"
+    )]
+    Capacity(Capacity),
 }
 
 #[derive(StructOpt)]
@@ -38,28 +85,349 @@ pub struct Encode {
     #[structopt(short, long, parse(from_os_str))]
     pub output: Option<PathBuf>,
 
-    /// Input file to encode, stdin if not present
-    #[structopt(short, long, parse(from_os_str), conflicts_with = "decode")]
-    pub input: Option<PathBuf>,
+    /// Input file to encode, stdin if not present. Several files or directories are packed into an archive
+    #[structopt(
+        short,
+        long,
+        parse(from_os_str),
+        number_of_values = 1,
+        conflicts_with = "decode"
+    )]
+    pub input: Vec<PathBuf>,
+
+    /// Hide the input file's name, permissions, modification time and content type along with it
+    #[structopt(short, long, requires = "input")]
+    pub embed_metadata: bool,
 
     /// Input image
     #[structopt(parse(from_os_str))]
     pub image: PathBuf,
 }
 
+#[derive(StructOpt)]
+pub struct Decode {
+    #[structopt(flatten)]
+    pub opts: DecodeOpts,
+
+    /// Only print metadata about the hidden payload, without writing it out
+    #[structopt(short = "M", long)]
+    pub metadata: bool,
+
+    /// Restore the hidden file under its original name, into the output directory if present
+    #[structopt(short, long)]
+    pub restore: bool,
+
+    /// List the files in a hidden archive
+    #[structopt(short, long, conflicts_with_all = &["metadata", "restore", "extract"])]
+    pub list: bool,
+
+    /// Extract the files in a hidden archive into a directory
+    #[structopt(short = "x", long, parse(from_os_str), conflicts_with_all = &["metadata", "restore"])]
+    pub extract: Option<PathBuf>,
+
+    /// Output file, stdout if not present
+    #[structopt(short, long, parse(from_os_str))]
+    pub output: Option<PathBuf>,
+
+    /// Input image
+    #[structopt(parse(from_os_str))]
+    pub image: PathBuf,
+
+    /// Files or directories to extract from a hidden archive, all if not present
+    #[structopt(requires = "extract")]
+    pub entries: Vec<String>,
+}
+
+impl From<Encode> for Decode {
+    fn from(encode: Encode) -> Self {
+        Decode {
+            opts: encode.opts.into(),
+            metadata: false,
+            restore: false,
+            list: false,
+            extract: None,
+            output: encode.output,
+            image: encode.image,
+            entries: Vec::new(),
+        }
+    }
+}
+
 #[derive(StructOpt)]
 pub struct Disguise {
     #[structopt(flatten)]
     pub opts: EncodeOpts,
 
+    /// Directory of cover images to hide files in, instead of fetching them from picsum.photos
+    #[structopt(long, parse(from_os_str), conflicts_with = "generate")]
+    pub covers: Option<PathBuf>,
+
+    /// Generate cover images of this kind to hide files in, instead of fetching them from picsum.photos
+    #[structopt(long, possible_values=&CoverKind::variants())]
+    pub generate: Option<CoverKind>,
+
+    /// Seed to generate the same cover images with, random if not given
+    #[structopt(long, requires = "generate")]
+    pub generate_seed: Option<String>,
+
+    /// How to pick a cover image from --covers for each file
+    #[structopt(long, requires = "covers", possible_values=&CoverSelection::variants())]
+    pub select: Option<CoverSelection>,
+
+    /// How to fit the cover image picked from --covers to the size needed
+    #[structopt(long, requires = "covers", possible_values=&CoverFit::variants())]
+    pub fit: Option<CoverFit>,
+
+    /// Disguise files in subdirectories too, leaving each image where its file was
+    #[structopt(short, long)]
+    pub recursive: bool,
+
+    /// Follow symbolic links to files and directories, instead of ignoring them
+    #[structopt(long)]
+    pub follow_symlinks: bool,
+
+    /// Include hidden files and directories, whose names start with a `.`
+    #[structopt(long)]
+    pub hidden: bool,
+
+    /// Only disguise files matching a glob pattern, relative to the directory, e.g. `*.pdf`
+    #[structopt(long, number_of_values = 1)]
+    pub include: Vec<String>,
+
+    /// Don't disguise files matching a glob pattern, relative to the directory, e.g. `drafts/**`
+    #[structopt(long, number_of_values = 1)]
+    pub exclude: Vec<String>,
+
+    /// How to name the images files are disguised as, the original names are only hidden inside them
+    #[structopt(long, possible_values=&CarrierNaming::variants())]
+    pub naming: Option<CarrierNaming>,
+
+    /// Number of files to disguise or restore at the same time
+    #[structopt(short, long, default_value = "1")]
+    pub jobs: usize,
+
+    /// Directory to write images to when disguising, or files to when restoring, instead of in place
+    #[structopt(long, parse(from_os_str))]
+    pub out_dir: Option<PathBuf>,
+
+    /// Keep the original files when disguising, or the images when restoring
+    #[structopt(long)]
+    pub keep_originals: bool,
+
+    /// List the files that would be disguised or restored, without changing anything
+    #[structopt(long)]
+    pub dry_run: bool,
+
+    /// Record the disguised files in a manifest encrypted with the key, to verify them when restored
+    #[structopt(long, requires = "key")]
+    pub manifest: bool,
+
+    /// Keep watching the directory, disguising files as soon as they are written to it
+    #[structopt(long, conflicts_with_all = &["decode", "dry-run"])]
+    pub watch: bool,
+
+    /// How long a file must go unchanged before it is disguised when watching [default: 2s]
+    #[structopt(long, requires = "watch", parse(try_from_str = humantime::parse_duration))]
+    pub debounce: Option<Duration>,
+
+    #[structopt(flatten)]
+    pub http: HttpOpts,
+
     /// Directory containing files to disguise
     #[structopt(parse(from_os_str))]
     pub dir: PathBuf,
 }
 
+/// Options for fetching cover images over HTTP
+#[derive(StructOpt, Debug, Clone)]
+pub struct HttpOpts {
+    /// URL of the image service to fetch cover images from, as `<url>/<width>` [default: `https://picsum.photos`]
+    #[structopt(long)]
+    pub image_url: Option<String>,
+
+    /// User agent to fetch cover images with
+    #[structopt(long)]
+    pub user_agent: Option<String>,
+
+    /// Time allowed to fetch each cover image, e.g. `10s` [default: 30s]
+    #[structopt(long, parse(try_from_str = humantime::parse_duration))]
+    pub timeout: Option<Duration>,
+
+    /// Number of times to retry fetching a cover image after a temporary failure [default: 2]
+    #[structopt(long)]
+    pub retries: Option<u32>,
+
+    /// Time to wait before retrying, doubled after each retry, e.g. `500ms` [default: 500ms]
+    #[structopt(long, parse(try_from_str = humantime::parse_duration))]
+    pub backoff: Option<Duration>,
+
+    /// Directory to cache fetched cover images in, reusing them for files needing the same size
+    #[structopt(long, parse(from_os_str))]
+    pub cache_dir: Option<PathBuf>,
+}
+
+impl From<HttpOpts> for ClientConfig {
+    fn from(opts: HttpOpts) -> Self {
+        let default = ClientConfig::default();
+        ClientConfig {
+            base_url: opts.image_url.unwrap_or(default.base_url),
+            user_agent: opts.user_agent.unwrap_or(default.user_agent),
+            timeout: opts.timeout.unwrap_or(default.timeout),
+            retries: opts.retries.unwrap_or(default.retries),
+            backoff: opts.backoff.unwrap_or(default.backoff),
+            cache_dir: opts.cache_dir,
+        }
+    }
+}
+
+/// Kinds of cover image that can be generated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CoverKind {
+    /// Fractal noise coloured like a landscape seen from above
+    Landscape,
+    /// A smooth gradient between two colours with grain
+    Gradient,
+    /// Overlapping waves of colour
+    Plasma,
+}
+
+impl FromStr for CoverKind {
+    type Err = String;
+    fn from_str(kind: &str) -> Result<Self, Self::Err> {
+        match kind {
+            "landscape" => Ok(Self::Landscape),
+            "gradient" => Ok(Self::Gradient),
+            "plasma" => Ok(Self::Plasma),
+            other => Err(format!("unknown cover kind: {}", other)),
+        }
+    }
+}
+
+impl CoverKind {
+    fn variants() -> [&'static str; 3] {
+        ["landscape", "gradient", "plasma"]
+    }
+}
+
+/// Ways to pick a cover image from a directory
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CoverSelection {
+    /// Any cover large enough, at random
+    #[default]
+    Random,
+    /// The smallest cover large enough
+    BestFit,
+}
+
+impl FromStr for CoverSelection {
+    type Err = String;
+    fn from_str(selection: &str) -> Result<Self, Self::Err> {
+        match selection {
+            "random" => Ok(Self::Random),
+            "best-fit" => Ok(Self::BestFit),
+            other => Err(format!("unknown cover selection: {}", other)),
+        }
+    }
+}
+
+impl CoverSelection {
+    fn variants() -> [&'static str; 2] {
+        ["random", "best-fit"]
+    }
+}
+
+/// Ways to fit a cover image to the size needed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CoverFit {
+    /// Use the cover as it is
+    #[default]
+    None,
+    /// Crop a square of the size needed from the centre of the cover
+    Crop,
+    /// Scale the cover to fill a square of the size needed, cropping any overflow
+    Resize,
+}
+
+impl FromStr for CoverFit {
+    type Err = String;
+    fn from_str(fit: &str) -> Result<Self, Self::Err> {
+        match fit {
+            "none" => Ok(Self::None),
+            "crop" => Ok(Self::Crop),
+            "resize" => Ok(Self::Resize),
+            other => Err(format!("unknown cover fit: {}", other)),
+        }
+    }
+}
+
+impl CoverFit {
+    fn variants() -> [&'static str; 3] {
+        ["none", "crop", "resize"]
+    }
+}
+
+/// Ways to name the images files are disguised as
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CarrierNaming {
+    /// Like a photo from a phone camera, e.g. `IMG_20240112_183344.png`, taken at a random time
+    #[default]
+    Plausible,
+    /// Random hexadecimal, e.g. `3f9a1c0b7e2d4a58.png`
+    Random,
+    /// The base64 encoded name of the original file, as written by earlier versions
+    Base64,
+}
+
+impl FromStr for CarrierNaming {
+    type Err = String;
+    fn from_str(naming: &str) -> Result<Self, Self::Err> {
+        match naming {
+            "plausible" => Ok(Self::Plausible),
+            "random" => Ok(Self::Random),
+            "base64" => Ok(Self::Base64),
+            other => Err(format!("unknown carrier naming: {}", other)),
+        }
+    }
+}
+
+impl CarrierNaming {
+    fn variants() -> [&'static str; 3] {
+        ["plausible", "random", "base64"]
+    }
+}
+
+#[derive(StructOpt)]
+pub struct Capacity {
+    /// Payload to plan for
+    #[structopt(
+        short,
+        long,
+        parse(from_os_str),
+        required_unless = "size",
+        conflicts_with = "size"
+    )]
+    pub input: Option<PathBuf>,
+
+    /// Size of the payload in bytes, when the payload itself is not at hand
+    #[structopt(short = "S", long)]
+    pub size: Option<usize>,
+
+    /// Account for encrypting the payload
+    #[structopt(short, long)]
+    pub encrypt: bool,
+
+    /// Account for base64 encoding the payload
+    #[structopt(short, long)]
+    pub base64: bool,
+
+    /// Cover image, or directory of cover images, to check the payload against
+    #[structopt(parse(from_os_str))]
+    pub cover: Option<PathBuf>,
+}
+
 #[derive(StructOpt, Clone)]
 pub struct EncodeOpts {
-    /// Decode a message from the image
+    /// Decode a message from the image (deprecated for `encode`, use the `decode` subcommand instead)
     #[structopt(short, long)]
     pub decode: bool,
 
@@ -76,7 +444,7 @@ pub struct EncodeOpts {
     pub key: Option<String>,
 
     /// Method to use for encoding [default=lsb]
-    #[structopt(short, long, possible_values=&StegMethod::variants())]
+    #[structopt(short, long, possible_values=&STEG_METHODS)]
     pub method: Option<StegMethod>,
 
     /// Method for bit distribution [default=sequential] [possible values: sequential, linear (linear-N when decoding)]
@@ -90,70 +458,103 @@ pub struct EncodeOpts {
     /// Maximum bit to possible modify
     #[structopt(short = "N", long, required_if("method", "rsb"), possible_values=&["1","2","3","4"])]
     pub max_bit: Option<u8>,
+
+    /// With `--decode`, reveal a message hidden with rsb by stegosaurust 0.4.6 or earlier
+    #[structopt(long, requires = "decode")]
+    pub legacy_rsb: bool,
 }
 
-/// Supported steganography encoding algorithms
-#[derive(StructOpt, Debug, Clone, Copy, Default)]
-pub enum StegMethod {
-    #[default]
-    /// Least significant bit encoding
-    ///
-    /// With a binary message, each bit of the message is encoded
-    /// into the least significant bit of each RGB byte of each pixel.
-    LeastSignificantBit,
-    /// Random significant bit encoding
-    ///
-    /// With a binary message, each bit of the message is encoded
-    /// randomly into one of the `n` least significant bits of each RGB byte of each pixel.
-    RandomSignificantBit,
-}
-
-impl FromStr for StegMethod {
-    type Err = String;
-    fn from_str(method: &str) -> Result<Self, Self::Err> {
-        match method {
-            "lsb" => Ok(Self::LeastSignificantBit),
-            "rsb" => Ok(Self::RandomSignificantBit),
-            other => Err(format!("unknown encoding method: {}", other)),
-        }
-    }
+#[derive(StructOpt, Clone)]
+pub struct DecodeOpts {
+    /// Decode with base64
+    #[structopt(short, long)]
+    pub base64: bool,
+
+    /// Decompress data
+    #[structopt(short, long)]
+    pub compress: bool,
+
+    /// Decrypt the text after decoding it with AES-256-CBC
+    #[structopt(short, long)]
+    pub key: Option<String>,
+
+    /// File containing the key to decrypt the text with
+    #[structopt(short, long, parse(from_os_str), conflicts_with = "key")]
+    pub identity: Option<PathBuf>,
+
+    /// Method the message was encoded with [default=lsb]
+    #[structopt(short, long, possible_values=&STEG_METHODS)]
+    pub method: Option<StegMethod>,
+
+    /// Method for bit distribution [default=sequential] [possible values: sequential, linear-N]
+    #[structopt(long)]
+    pub distribution: Option<BitDistribution>,
+
+    /// Seed for random significant bit decoding
+    #[structopt(short, long, required_if("method", "rsb"))]
+    pub seed: Option<String>,
+
+    /// Maximum bit possibly modified when encoding
+    #[structopt(short = "N", long, required_if("method", "rsb"), possible_values=&["1","2","3","4"])]
+    pub max_bit: Option<u8>,
+
+    /// Reveal a message hidden with rsb by stegosaurust 0.4.6 or earlier
+    #[structopt(long)]
+    pub legacy_rsb: bool,
 }
 
-impl StegMethod {
-    fn variants() -> [&'static str; 2] {
-        ["lsb", "rsb"]
+impl From<EncodeOpts> for DecodeOpts {
+    fn from(opts: EncodeOpts) -> Self {
+        DecodeOpts {
+            base64: opts.base64,
+            compress: opts.compress,
+            key: opts.key,
+            identity: None,
+            method: opts.method,
+            distribution: opts.distribution,
+            seed: opts.seed,
+            max_bit: opts.max_bit,
+            legacy_rsb: opts.legacy_rsb,
+        }
     }
 }
 
-/// Supported bit encoding bit distribution methods
-#[derive(StructOpt, Debug, Clone, Default)]
-pub enum BitDistribution {
-    #[default]
-    /// Encode bits sequentially into the image starting from top-left
-    Sequential,
-    /// Evenly space out the bits in the image so not all packed into top-left
-    Linear { length: usize },
+impl From<DecodeOpts> for EncodeOpts {
+    fn from(opts: DecodeOpts) -> Self {
+        EncodeOpts {
+            decode: true,
+            base64: opts.base64,
+            compress: opts.compress,
+            key: opts.key,
+            method: opts.method,
+            distribution: opts.distribution,
+            seed: opts.seed,
+            max_bit: opts.max_bit,
+            legacy_rsb: opts.legacy_rsb,
+        }
+    }
 }
 
-impl FromStr for BitDistribution {
-    type Err = String;
-    fn from_str(method: &str) -> Result<Self, Self::Err> {
-        let parts: Vec<&str> = method.split('-').collect();
-        let method = if parts.len() <= 1 { method } else { parts[0] };
-        match method {
-            "sequential" => Ok(Self::Sequential),
-            "linear" => {
-                let length = *(parts.get(1).unwrap_or(&"0"));
-                let length = length.parse::<usize>().unwrap_or_else(|err| {
-                    eprintln!(
-                        "error parsing message length in linear bit distribution: {}",
-                        err
-                    );
-                    std::process::exit(1);
-                });
-                Ok(Self::Linear { length })
-            }
-            other => Err(format!("unknown bit distribution {}", other)),
+impl From<&EncodeOpts> for StegoBuilder {
+    fn from(opts: &EncodeOpts) -> Self {
+        let mut builder = Stego::builder()
+            .method(opts.method.unwrap_or_default())
+            .distribution(opts.distribution.clone().unwrap_or_default())
+            .legacy_rsb(opts.legacy_rsb)
+            .compress(opts.compress)
+            .base64(opts.base64);
+        if let Some(seed) = &opts.seed {
+            builder = builder.seed(seed);
+        }
+        if let Some(max_bit) = opts.max_bit {
+            builder = builder.max_bit(max_bit);
         }
+        if let Some(key) = &opts.key {
+            builder = builder.encrypt(key);
+        }
+        builder
     }
 }
+
+/// Names of the steganography methods, as parsed by `StegMethod::from_str`
+const STEG_METHODS: [&str; 2] = ["lsb", "rsb"];
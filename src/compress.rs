@@ -1,5 +1,7 @@
 use crate::CompressionError;
-use compression::prelude::*;
+use bzip2::read::{BzDecoder, BzEncoder};
+use bzip2::Compression;
+use std::io::Read;
 
 /// compress a slice of bytes into a new vec of bytes
 pub fn compress(data: &[u8]) -> Result<Vec<u8>, CompressionError> {
@@ -7,11 +9,11 @@ pub fn compress(data: &[u8]) -> Result<Vec<u8>, CompressionError> {
         return Err(CompressionError::EmptyData);
     }
 
-    data.iter()
-        .cloned()
-        .encode(&mut BZip2Encoder::new(9), Action::Finish)
-        .collect::<Result<Vec<_>, _>>()
-        .map_err(CompressionError::Compression)
+    let mut compressed = Vec::new();
+    BzEncoder::new(data, Compression::best())
+        .read_to_end(&mut compressed)
+        .map_err(|e| CompressionError::Compression(e.to_string()))?;
+    Ok(compressed)
 }
 
 /// decompress a slice of bytes into a new vec of bytes
@@ -20,11 +22,11 @@ pub fn decompress(data: &[u8]) -> Result<Vec<u8>, CompressionError> {
         return Err(CompressionError::EmptyData);
     }
 
-    data.iter()
-        .cloned()
-        .decode(&mut BZip2Decoder::new())
-        .collect::<Result<Vec<_>, _>>()
-        .map_err(CompressionError::Decompression)
+    let mut decompressed = Vec::new();
+    BzDecoder::new(data)
+        .read_to_end(&mut decompressed)
+        .map_err(|e| CompressionError::Decompression(e.to_string()))?;
+    Ok(decompressed)
 }
 
 #[cfg(test)]
@@ -45,6 +47,20 @@ mod tests {
         assert_eq!(decompress(&output).unwrap(), input.as_bytes());
     }
 
+    #[test]
+    fn test_truncated_decompression() {
+        let input = "a".repeat(100);
+        let output = compress(input.as_bytes()).unwrap();
+        let result = decompress(&output[..output.len() / 2]);
+        assert!(matches!(result, Err(CompressionError::Decompression(_))));
+    }
+
+    #[test]
+    fn test_invalid_decompression() {
+        let result = decompress(b"not bzip2 data");
+        assert!(matches!(result, Err(CompressionError::Decompression(_))));
+    }
+
     #[test]
     fn test_empty_input_compression() {
         let input = "";
@@ -0,0 +1,475 @@
+use std::ffi::{c_char, c_int, CStr};
+use std::panic::{self, UnwindSafe};
+use std::{ptr, slice};
+
+use crate::steganography::{max_len, BitDistribution, EmbedError, StegMethod};
+use crate::stego::Stego;
+use crate::{CompressionError, CryptoError, StegError};
+
+/// Hide bits in the least significant bit of each colour value
+pub const STEG_METHOD_LSB: u32 = 0;
+/// Hide bits in a random bit, up to `max_bit`, of each colour value
+pub const STEG_METHOD_RSB: u32 = 1;
+
+/// Hide bits in consecutive colour values
+pub const STEG_DISTRIBUTION_SEQUENTIAL: u32 = 0;
+/// Spread bits evenly across the image
+pub const STEG_DISTRIBUTION_LINEAR: u32 = 1;
+
+/// Outcome of a call, mapped from the errors of the library.
+///
+/// Values are stable: new ones are only ever added.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StegStatus {
+    Ok = 0,
+    /// A required pointer was null
+    NullPointer = 1,
+    /// A string was not valid UTF-8
+    InvalidUtf8 = 2,
+    /// The options were not valid, e.g. rsb without a seed
+    InvalidConfig = 3,
+    /// The payload did not fit in the image
+    Encoding = 10,
+    /// No hidden data was found in the image
+    EncodingNotFound = 11,
+    Decoding = 12,
+    Verification = 13,
+    Base64 = 14,
+    Compression = 20,
+    Decompression = 21,
+    CompressionEmptyData = 22,
+    CryptoSalt = 30,
+    CryptoPasswordHash = 31,
+    CryptoCipher = 32,
+    /// Decrypting failed, most likely with the wrong key
+    CryptoDecryption = 33,
+    CryptoUnknown = 34,
+    /// The library panicked, which is a bug
+    Panic = 98,
+    Unknown = 99,
+}
+
+impl From<&CompressionError> for StegStatus {
+    fn from(err: &CompressionError) -> Self {
+        match err {
+            CompressionError::Compression(_) => StegStatus::Compression,
+            CompressionError::Decompression(_) => StegStatus::Decompression,
+            CompressionError::EmptyData => StegStatus::CompressionEmptyData,
+        }
+    }
+}
+
+impl From<&CryptoError> for StegStatus {
+    fn from(err: &CryptoError) -> Self {
+        match err {
+            CryptoError::Salt => StegStatus::CryptoSalt,
+            CryptoError::PasswordHash => StegStatus::CryptoPasswordHash,
+            CryptoError::Cipher(_) => StegStatus::CryptoCipher,
+            CryptoError::Decryption(_) => StegStatus::CryptoDecryption,
+            CryptoError::Unknown => StegStatus::CryptoUnknown,
+        }
+    }
+}
+
+impl From<&StegError> for StegStatus {
+    fn from(err: &StegError) -> Self {
+        match err {
+            StegError::EncodingNotFound => StegStatus::EncodingNotFound,
+            StegError::Encoding(_) => StegStatus::Encoding,
+            StegError::Decoding(_) => StegStatus::Decoding,
+            StegError::Compression(err) => err.into(),
+            StegError::Crypto(err) => err.into(),
+            StegError::Verification(_) => StegStatus::Verification,
+            StegError::Base64(_) => StegStatus::Base64,
+            StegError::InvalidConfig(_) => StegStatus::InvalidConfig,
+            StegError::Unknown => StegStatus::Unknown,
+        }
+    }
+}
+
+impl From<StegError> for StegStatus {
+    fn from(err: StegError) -> Self {
+        (&err).into()
+    }
+}
+
+impl From<EmbedError> for StegStatus {
+    fn from(err: EmbedError) -> Self {
+        StegError::from(err).into()
+    }
+}
+
+/// How to hide and reveal a payload, as the options of `stegosaurust encode` and `decode`
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct StegOptions {
+    /// `STEG_METHOD_LSB` or `STEG_METHOD_RSB`
+    pub method: u32,
+    /// `STEG_DISTRIBUTION_SEQUENTIAL` or `STEG_DISTRIBUTION_LINEAR`
+    pub distribution: u32,
+    /// Length of a linear distribution, needed to decode, from `stegosaurust encode` or `steg_linear_length`
+    pub linear_length: usize,
+    /// Seed for random significant bit encoding, may be null otherwise
+    pub seed: *const c_char,
+    /// Maximum bit (1-4) to hide bits in with random significant bit encoding
+    pub max_bit: u8,
+    /// Compress the payload
+    pub compress: bool,
+    /// Key to encrypt the payload with using AES-256-CBC, null not to encrypt it
+    pub key: *const c_char,
+    /// Base64 encode the payload
+    pub base64: bool,
+}
+
+/// Options hiding payloads in the least significant bits, sequentially, without transforming them
+#[no_mangle]
+pub extern "C" fn steg_options_default() -> StegOptions {
+    StegOptions {
+        method: STEG_METHOD_LSB,
+        distribution: STEG_DISTRIBUTION_SEQUENTIAL,
+        linear_length: 0,
+        seed: ptr::null(),
+        max_bit: 0,
+        compress: false,
+        key: ptr::null(),
+        base64: false,
+    }
+}
+
+impl StegStatus {
+    /// Static description of the status
+    fn message(self) -> &'static CStr {
+        match self {
+            StegStatus::Ok => c"ok",
+            StegStatus::NullPointer => c"a required pointer was null",
+            StegStatus::InvalidUtf8 => c"a string was not valid UTF-8",
+            StegStatus::InvalidConfig => c"invalid options",
+            StegStatus::Encoding => c"the payload does not fit in the image",
+            StegStatus::EncodingNotFound => c"no hidden data found in the image",
+            StegStatus::Decoding => c"failed to decode the hidden data",
+            StegStatus::Verification => c"the hidden data failed verification",
+            StegStatus::Base64 => c"the hidden data is not valid base64",
+            StegStatus::Compression => c"failed to compress the payload",
+            StegStatus::Decompression => c"failed to decompress the hidden data",
+            StegStatus::CompressionEmptyData => c"no data to compress",
+            StegStatus::CryptoSalt => c"failed to get random salt",
+            StegStatus::CryptoPasswordHash => c"failed to hash the key",
+            StegStatus::CryptoCipher => c"failed to create the cipher",
+            StegStatus::CryptoDecryption => c"failed to decrypt the hidden data",
+            StegStatus::CryptoUnknown => c"unknown cryptography error",
+            StegStatus::Panic => c"stegosaurust panicked",
+            StegStatus::Unknown => c"unknown error",
+        }
+    }
+}
+
+/// Static, null terminated description of a status, or of an unknown status if `status` is not a `StegStatus`
+#[no_mangle]
+pub extern "C" fn steg_status_message(status: c_int) -> *const c_char {
+    // every status, to look up the one passed by its value
+    const STATUSES: [StegStatus; 19] = [
+        StegStatus::Ok,
+        StegStatus::NullPointer,
+        StegStatus::InvalidUtf8,
+        StegStatus::InvalidConfig,
+        StegStatus::Encoding,
+        StegStatus::EncodingNotFound,
+        StegStatus::Decoding,
+        StegStatus::Verification,
+        StegStatus::Base64,
+        StegStatus::Compression,
+        StegStatus::Decompression,
+        StegStatus::CompressionEmptyData,
+        StegStatus::CryptoSalt,
+        StegStatus::CryptoPasswordHash,
+        StegStatus::CryptoCipher,
+        StegStatus::CryptoDecryption,
+        StegStatus::CryptoUnknown,
+        StegStatus::Panic,
+        StegStatus::Unknown,
+    ];
+    STATUSES
+        .into_iter()
+        .find(|known| *known as c_int == status)
+        .map_or(c"unknown status", StegStatus::message)
+        .as_ptr()
+}
+
+/// Maximum length of a payload, once transformed, that can be hidden in an image of `width` by `height` pixels
+#[no_mangle]
+pub extern "C" fn steg_capacity(width: u32, height: u32) -> usize {
+    max_len(width, height)
+}
+
+/// Length of the linear distribution a payload hidden with `STEG_DISTRIBUTION_LINEAR` is spread over,
+/// from the length of the payload once transformed
+#[no_mangle]
+pub extern "C" fn steg_linear_length(encoded_len: usize) -> usize {
+    crate::steganography::linear_length(encoded_len)
+}
+
+/// Hide a payload in the raw buffer of an RGB image, with three colour values to a pixel, in place.
+///
+/// On success `*encoded_len`, unless null, is the length of the payload once transformed, as hidden in the image,
+/// to pass to `steg_linear_length` when it was hidden with `STEG_DISTRIBUTION_LINEAR`.
+///
+/// # Safety
+///
+/// `options` must point to valid options, with null or null terminated strings, `pixels` to `pixels_len` bytes,
+/// `payload` to `payload_len` bytes, or be null if `payload_len` is 0, and `encoded_len` to a writable location
+/// or be null.
+#[no_mangle]
+pub unsafe extern "C" fn steg_encode(
+    options: *const StegOptions,
+    pixels: *mut u8,
+    pixels_len: usize,
+    payload: *const u8,
+    payload_len: usize,
+    encoded_len: *mut usize,
+) -> StegStatus {
+    catch(|| {
+        let stego = stego(options)?;
+        let pixels = slice_mut(pixels, pixels_len)?;
+        let payload = slice(payload, payload_len)?;
+        let data = stego.pack(payload)?;
+        stego.encoder().embed(pixels, &data)?;
+        if let Some(encoded_len) = encoded_len.as_mut() {
+            *encoded_len = data.len();
+        }
+        Ok(())
+    })
+}
+
+/// Reveal the payload hidden in the raw buffer of an RGB image, with three colour values to a pixel.
+///
+/// On success `*payload` points to `*payload_len` bytes, to free with `steg_bytes_free`.
+///
+/// # Safety
+///
+/// `options` must point to valid options, with null or null terminated strings, `pixels` to `pixels_len` bytes,
+/// and `payload` and `payload_len` to writable locations.
+#[no_mangle]
+pub unsafe extern "C" fn steg_decode(
+    options: *const StegOptions,
+    pixels: *const u8,
+    pixels_len: usize,
+    payload: *mut *mut u8,
+    payload_len: *mut usize,
+) -> StegStatus {
+    catch(|| {
+        if payload.is_null() || payload_len.is_null() {
+            return Err(StegStatus::NullPointer);
+        }
+        let stego = stego(options)?;
+        let pixels = slice(pixels, pixels_len)?;
+        let data = stego.unpack(stego.encoder().extract(pixels)?)?;
+        let data = Box::into_raw(data.into_boxed_slice());
+        *payload_len = data.len();
+        *payload = data.cast();
+        Ok(())
+    })
+}
+
+/// Free a payload returned by `steg_decode`.
+///
+/// # Safety
+///
+/// `payload` and `payload_len` must have been returned by `steg_decode` and not freed already, or `payload` be null.
+#[no_mangle]
+pub unsafe extern "C" fn steg_bytes_free(payload: *mut u8, payload_len: usize) {
+    if !payload.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(
+            payload,
+            payload_len,
+        )));
+    }
+}
+
+/// Run `f`, turning a panic into a status rather than unwinding into C
+fn catch(f: impl FnOnce() -> Result<(), StegStatus> + UnwindSafe) -> StegStatus {
+    match panic::catch_unwind(f) {
+        Ok(Ok(())) => StegStatus::Ok,
+        Ok(Err(status)) => status,
+        Err(_) => StegStatus::Panic,
+    }
+}
+
+unsafe fn stego(options: *const StegOptions) -> Result<Stego, StegStatus> {
+    let options = options.as_ref().ok_or(StegStatus::NullPointer)?;
+    let method = match options.method {
+        STEG_METHOD_LSB => StegMethod::LeastSignificantBit,
+        STEG_METHOD_RSB => StegMethod::RandomSignificantBit,
+        _ => return Err(StegStatus::InvalidConfig),
+    };
+    let distribution = match options.distribution {
+        STEG_DISTRIBUTION_SEQUENTIAL => BitDistribution::Sequential,
+        STEG_DISTRIBUTION_LINEAR => BitDistribution::Linear {
+            length: options.linear_length,
+        },
+        _ => return Err(StegStatus::InvalidConfig),
+    };
+    let mut builder = Stego::builder()
+        .method(method)
+        .distribution(distribution)
+        .max_bit(options.max_bit)
+        .compress(options.compress)
+        .base64(options.base64);
+    if let Some(seed) = string(options.seed)? {
+        builder = builder.seed(seed);
+    }
+    if let Some(key) = string(options.key)? {
+        builder = builder.encrypt(key);
+    }
+    Ok(builder.build()?)
+}
+
+unsafe fn string<'a>(s: *const c_char) -> Result<Option<&'a str>, StegStatus> {
+    if s.is_null() {
+        return Ok(None);
+    }
+    CStr::from_ptr(s)
+        .to_str()
+        .map(Some)
+        .map_err(|_| StegStatus::InvalidUtf8)
+}
+
+unsafe fn slice<'a>(data: *const u8, len: usize) -> Result<&'a [u8], StegStatus> {
+    match (data.is_null(), len) {
+        (true, 0) => Ok(&[]),
+        (true, _) => Err(StegStatus::NullPointer),
+        (false, _) => Ok(slice::from_raw_parts(data, len)),
+    }
+}
+
+unsafe fn slice_mut<'a>(data: *mut u8, len: usize) -> Result<&'a mut [u8], StegStatus> {
+    match (data.is_null(), len) {
+        (true, 0) => Ok(&mut []),
+        (true, _) => Err(StegStatus::NullPointer),
+        (false, _) => Ok(slice::from_raw_parts_mut(data, len)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CString;
+
+    #[test]
+    fn test_encode_decode() {
+        let seed = CString::new("seed").unwrap();
+        let key = CString::new("secret").unwrap();
+        let mut options = steg_options_default();
+        options.method = STEG_METHOD_RSB;
+        options.seed = seed.as_ptr();
+        options.max_bit = 2;
+        options.compress = true;
+        options.key = key.as_ptr();
+        options.base64 = true;
+        options.distribution = STEG_DISTRIBUTION_LINEAR;
+
+        let msg = b"hello world";
+        let mut pixels = vec![0; 64 * 64 * 3];
+        let mut encoded_len = 0;
+        let status = unsafe {
+            steg_encode(
+                &options,
+                pixels.as_mut_ptr(),
+                pixels.len(),
+                msg.as_ptr(),
+                msg.len(),
+                &mut encoded_len,
+            )
+        };
+        assert_eq!(status, StegStatus::Ok);
+        options.linear_length = steg_linear_length(encoded_len);
+
+        let mut payload = ptr::null_mut();
+        let mut payload_len = 0;
+        let status = unsafe {
+            steg_decode(
+                &options,
+                pixels.as_ptr(),
+                pixels.len(),
+                &mut payload,
+                &mut payload_len,
+            )
+        };
+        assert_eq!(status, StegStatus::Ok);
+        assert_eq!(unsafe { slice::from_raw_parts(payload, payload_len) }, msg);
+        unsafe { steg_bytes_free(payload, payload_len) };
+
+        options.key = ptr::null();
+        let status = unsafe {
+            steg_decode(
+                &options,
+                pixels.as_ptr(),
+                pixels.len(),
+                &mut payload,
+                &mut payload_len,
+            )
+        };
+        assert_eq!(status, StegStatus::Decompression);
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut pixels = vec![0; 8 * 8 * 3];
+        let mut options = steg_options_default();
+        let msg = vec![1; steg_capacity(8, 8) + 1];
+        let encode = |options: &StegOptions, pixels: &mut Vec<u8>| unsafe {
+            steg_encode(
+                options,
+                pixels.as_mut_ptr(),
+                pixels.len(),
+                msg.as_ptr(),
+                msg.len(),
+                ptr::null_mut(),
+            )
+        };
+        assert_eq!(encode(&options, &mut pixels), StegStatus::Encoding);
+        assert_eq!(
+            unsafe {
+                steg_encode(
+                    ptr::null(),
+                    pixels.as_mut_ptr(),
+                    pixels.len(),
+                    ptr::null(),
+                    0,
+                    ptr::null_mut(),
+                )
+            },
+            StegStatus::NullPointer
+        );
+        options.method = STEG_METHOD_RSB;
+        assert_eq!(encode(&options, &mut pixels), StegStatus::InvalidConfig);
+        options.method = 7;
+        assert_eq!(encode(&options, &mut pixels), StegStatus::InvalidConfig);
+
+        let mut payload = ptr::null_mut();
+        let mut payload_len = 0;
+        let status = unsafe {
+            steg_decode(
+                &steg_options_default(),
+                pixels.as_ptr(),
+                pixels.len(),
+                &mut payload,
+                &mut payload_len,
+            )
+        };
+        assert_eq!(status, StegStatus::EncodingNotFound);
+    }
+
+    #[test]
+    fn test_status_message() {
+        let message = |status| unsafe { CStr::from_ptr(steg_status_message(status)) };
+        assert_eq!(message(StegStatus::Ok as c_int), c"ok");
+        assert_eq!(
+            message(StegStatus::CryptoDecryption as c_int),
+            c"failed to decrypt the hidden data"
+        );
+        for unknown in [-1, 4, 97, c_int::MAX] {
+            assert_eq!(message(unknown), c"unknown status");
+        }
+    }
+}
@@ -1,59 +1,463 @@
-use reqwest;
+use image::imageops::FilterType;
+use image::ImageOutputFormat;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use rand_pcg::Pcg64;
+use rand_seeder::Seeder;
+#[cfg(feature = "net")]
 use reqwest::blocking::Client;
-use reqwest::header::USER_AGENT;
+#[cfg(feature = "net")]
+use reqwest::StatusCode;
+#[cfg(feature = "net")]
+use sha2::{Digest, Sha256};
 use std::error::Error;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-pub trait ImageApi {
+use crate::cli::{CoverFit, CoverKind, CoverSelection};
+
+/// A source of cover images, shared between the threads disguising files
+pub trait ImageApi: Send + Sync {
     fn get_square_image(&self, width: usize) -> Result<Vec<u8>, Box<dyn Error>>;
+
+    /// Get a square image to hide the file identified by `key` in, such as its path and part.
+    ///
+    /// Sources that create images rather than fetch them use the key, so each file gets the same image
+    /// whatever order the files are hidden in.
+    fn get_square_image_for(&self, _key: &str, width: usize) -> Result<Vec<u8>, Box<dyn Error>> {
+        self.get_square_image(width)
+    }
+}
+
+/// Settings for fetching images over HTTP from picsum.photos, or any service serving square images at `<base url>/<width>`
+#[derive(Debug, Clone)]
+pub struct ClientConfig {
+    /// URL that the width of the image is appended to
+    pub base_url: String,
+    pub user_agent: String,
+    /// Time allowed for each request to complete
+    pub timeout: Duration,
+    /// Number of times to retry a request that failed to connect, timed out or got a server error
+    pub retries: u32,
+    /// Time to wait before the first retry, doubled for each retry after
+    pub backoff: Duration,
+    /// Directory to cache fetched images in, keyed by their URL.
+    ///
+    /// Images are reused for every request of the same width, so the cache suits services that serve the same
+    /// image for a URL rather than a random one.
+    pub cache_dir: Option<PathBuf>,
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        ClientConfig {
+            base_url: String::from("https://picsum.photos"),
+            user_agent: String::from(
+                "Mozilla/5.0 (Windows NT 10.0; rv:91.0) Gecko/20100101 Firefox/91.0",
+            ),
+            timeout: Duration::from_secs(30),
+            retries: 2,
+            backoff: Duration::from_millis(500),
+            cache_dir: None,
+        }
+    }
 }
 
+/// Fetches cover images over HTTP, from picsum.photos unless configured otherwise, with the `net` feature
+#[cfg(feature = "net")]
 pub struct PicsumClient {
     http_client: Client,
-    base_url: String,
+    config: ClientConfig,
 }
 
+#[cfg(feature = "net")]
 impl PicsumClient {
     pub fn new() -> Self {
-        PicsumClient {
-            http_client: Client::new(),
-            base_url: String::from("https://picsum.photos"),
+        // SAFETY: the default config only has a valid user agent and timeout
+        Self::with_config(ClientConfig::default()).unwrap()
+    }
+
+    pub fn with_config(config: ClientConfig) -> reqwest::Result<Self> {
+        let http_client = Client::builder()
+            .user_agent(config.user_agent.clone())
+            .timeout(config.timeout)
+            .build()?;
+        Ok(PicsumClient {
+            http_client,
+            config,
+        })
+    }
+
+    /// Path an image fetched from `url` is cached at
+    fn cache_path(&self, url: &str) -> Option<PathBuf> {
+        let digest = Sha256::digest(url.as_bytes());
+        let name = digest
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>();
+        self.config.cache_dir.as_ref().map(|dir| dir.join(name))
+    }
+
+    /// Fetch the image at `url`, retrying with backoff on errors that could be temporary
+    fn fetch(&self, url: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut attempt = 0;
+        loop {
+            let retry = match self.http_client.get(url).send() {
+                Ok(response) if response.status().is_success() => {
+                    return Ok(response.bytes()?.to_vec());
+                }
+                Ok(response) => {
+                    let status = response.status();
+                    let err: Box<dyn Error> =
+                        Box::from(format!("{}:{}", status.as_str(), response.text()?));
+                    if !(status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS) {
+                        return Err(err);
+                    }
+                    err
+                }
+                // the blocking client reports timeouts as errors sending the request
+                Err(err) if err.is_connect() || err.is_timeout() || err.is_request() => {
+                    Box::from(err)
+                }
+                Err(err) => return Err(Box::from(err)),
+            };
+            if attempt >= self.config.retries {
+                return Err(retry);
+            }
+            let backoff = self.config.backoff * 2u32.pow(attempt);
+            log::warn!(
+                "fetching {} failed, retrying in {:?}: {}",
+                url,
+                backoff,
+                retry
+            );
+            std::thread::sleep(backoff);
+            attempt += 1;
         }
     }
 }
 
+#[cfg(feature = "net")]
 impl std::default::Default for PicsumClient {
     fn default() -> Self {
         Self::new()
     }
 }
 
+#[cfg(feature = "net")]
 impl ImageApi for PicsumClient {
     fn get_square_image(&self, width: usize) -> Result<Vec<u8>, Box<dyn Error>> {
-        let request_url = format!("{}/{}", self.base_url, width);
-        let response = self
-            .http_client
-            .get(request_url)
-            .header(
-                USER_AGENT,
-                "Mozilla/5.0 (Windows NT 10.0; rv:91.0) Gecko/20100101 Firefox/91.0",
+        let request_url = format!("{}/{}", self.config.base_url, width);
+        let cache_path = self.cache_path(&request_url);
+        if let Some(path) = &cache_path {
+            if let Ok(bytes) = std::fs::read(path) {
+                log::debug!("using cached image {} for {}", path.display(), request_url);
+                return Ok(bytes);
+            }
+        }
+        let bytes = self.fetch(&request_url)?;
+        if let Some(path) = &cache_path {
+            write_cache(path, &bytes)?;
+        }
+        Ok(bytes)
+    }
+}
+
+/// Write a cached image by writing a temporary file next to it and renaming it into place,
+/// so a request made at the same time, or after an interrupted run, never reads a partly written image.
+#[cfg(feature = "net")]
+fn write_cache(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    std::fs::create_dir_all(path.parent().unwrap_or(Path::new(".")))?;
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    // requests for the same image may be made by several threads at once
+    let tmp = path.with_file_name(format!(".{}.{:016x}.tmp", name, rand::random::<u64>()));
+    let result = std::fs::write(&tmp, bytes).and_then(|_| std::fs::rename(&tmp, path));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+    result
+}
+
+/// A cover image in a directory of `LocalImages`
+struct Cover {
+    path: PathBuf,
+    width: u32,
+    height: u32,
+}
+
+impl Cover {
+    /// Whether the cover can provide a square image of the given width once fit
+    fn fits(&self, width: u32, fit: CoverFit) -> bool {
+        match fit {
+            CoverFit::None => self.width as u64 * self.height as u64 >= width as u64 * width as u64,
+            CoverFit::Crop | CoverFit::Resize => self.width >= width && self.height >= width,
+        }
+    }
+
+    fn area(&self) -> u64 {
+        self.width as u64 * self.height as u64
+    }
+}
+
+/// Cover images read from a local directory, for use without access to the internet
+pub struct LocalImages {
+    covers: Vec<Cover>,
+    selection: CoverSelection,
+    fit: CoverFit,
+}
+
+impl LocalImages {
+    /// Find the images in `dir` to use as covers, ignoring hidden files and anything that is not an image.
+    pub fn new(dir: &Path, selection: CoverSelection, fit: CoverFit) -> std::io::Result<Self> {
+        let mut covers = Vec::new();
+        for dirent in std::fs::read_dir(dir)? {
+            let path = dirent?.path();
+            let hidden = path
+                .file_name()
+                .map(|n| n.to_string_lossy().starts_with('.'))
+                .unwrap_or(true);
+            if hidden || !path.is_file() {
+                continue;
+            }
+            match image::image_dimensions(&path) {
+                Ok((width, height)) => covers.push(Cover {
+                    path,
+                    width,
+                    height,
+                }),
+                Err(err) => log::debug!("not using {} as a cover: {}", path.display(), err),
+            }
+        }
+        covers.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(LocalImages {
+            covers,
+            selection,
+            fit,
+        })
+    }
+
+    /// Pick a cover that can provide a square image of the given width
+    fn select(&self, width: u32) -> Option<&Cover> {
+        let candidates = self
+            .covers
+            .iter()
+            .filter(|c| c.fits(width, self.fit))
+            .collect::<Vec<_>>();
+        let picked = match self.selection {
+            CoverSelection::Random => candidates.choose(&mut rand::thread_rng()).copied(),
+            CoverSelection::BestFit => candidates.into_iter().min_by_key(|c| c.area()),
+        };
+        // any cover can be scaled up when resizing, so fall back to the largest
+        match (picked, self.fit) {
+            (None, CoverFit::Resize) => self.covers.iter().max_by_key(|c| c.area()),
+            (picked, _) => picked,
+        }
+    }
+}
+
+impl ImageApi for LocalImages {
+    fn get_square_image(&self, width: usize) -> Result<Vec<u8>, Box<dyn Error>> {
+        let width = width as u32;
+        let cover = self.select(width).ok_or_else(|| {
+            format!(
+                "no cover image large enough for a {}x{} image, out of {} covers",
+                width,
+                width,
+                self.covers.len()
             )
-            .send()?;
-        if response.status().is_success() {
-            let bytes = response.bytes()?.to_vec();
-            Ok(bytes)
-        } else {
-            Err(Box::from(format!(
-                "{}:{}",
-                response.status().as_str(),
-                response.text()?
-            )))
+        })?;
+        log::debug!("using cover {}", cover.path.display());
+        let img = match self.fit {
+            CoverFit::None => return Ok(std::fs::read(&cover.path)?),
+            CoverFit::Crop => image::open(&cover.path)?.crop_imm(
+                (cover.width - width) / 2,
+                (cover.height - width) / 2,
+                width,
+                width,
+            ),
+            CoverFit::Resize => {
+                image::open(&cover.path)?.resize_to_fill(width, width, FilterType::Lanczos3)
+            }
+        };
+        let mut bytes = Vec::new();
+        img.write_to(&mut Cursor::new(&mut bytes), ImageOutputFormat::Png)?;
+        Ok(bytes)
+    }
+}
+
+/// Cover images generated procedurally, for use without access to the internet or any images.
+///
+/// Each image is generated from the seed, the key of the file it is for and its width,
+/// so the same seed gives every file the same image, however many files are hidden at a time.
+pub struct GeneratedImages {
+    kind: CoverKind,
+    seed: String,
+}
+
+impl GeneratedImages {
+    pub fn new(kind: CoverKind, seed: &str) -> Self {
+        GeneratedImages {
+            kind,
+            seed: seed.to_string(),
         }
     }
+
+    /// Generate the image for the file identified by `key`
+    pub fn generate(&self, key: &str, width: u32) -> image::RgbImage {
+        let mut rng: Pcg64 = Seeder::from((&self.seed, key, width)).make_rng();
+        let mut img = match self.kind {
+            CoverKind::Landscape => landscape(&mut rng, width),
+            CoverKind::Gradient => gradient(&mut rng, width),
+            CoverKind::Plasma => plasma(&mut rng, width),
+        };
+        // grain, as a camera sensor would add
+        for value in img.iter_mut() {
+            *value = (*value as i16 + rng.gen_range(-3..=3)).clamp(0, 255) as u8;
+        }
+        img
+    }
+}
+
+impl ImageApi for GeneratedImages {
+    fn get_square_image(&self, width: usize) -> Result<Vec<u8>, Box<dyn Error>> {
+        self.get_square_image_for("", width)
+    }
+
+    fn get_square_image_for(&self, key: &str, width: usize) -> Result<Vec<u8>, Box<dyn Error>> {
+        let img = self.generate(key, width as u32);
+        let mut bytes = Vec::new();
+        image::DynamicImage::ImageRgb8(img)
+            .write_to(&mut Cursor::new(&mut bytes), ImageOutputFormat::Png)?;
+        Ok(bytes)
+    }
+}
+
+/// A grid of random values, smoothly interpolated between grid points
+struct ValueNoise {
+    cells: usize,
+    values: Vec<f64>,
+}
+
+impl ValueNoise {
+    fn new(rng: &mut Pcg64, cells: usize) -> Self {
+        let values = (0..(cells + 1) * (cells + 1)).map(|_| rng.gen()).collect();
+        ValueNoise { cells, values }
+    }
+
+    /// Noise in `0..1` at `x` and `y` in `0..1`
+    fn at(&self, x: f64, y: f64) -> f64 {
+        let (x, y) = (x * self.cells as f64, y * self.cells as f64);
+        let (x0, y0) = (
+            (x as usize).min(self.cells - 1),
+            (y as usize).min(self.cells - 1),
+        );
+        let smooth = |t: f64| t * t * (3. - 2. * t);
+        let (tx, ty) = (smooth(x - x0 as f64), smooth(y - y0 as f64));
+        let value = |x: usize, y: usize| self.values[y * (self.cells + 1) + x];
+        let top = value(x0, y0) + (value(x0 + 1, y0) - value(x0, y0)) * tx;
+        let bottom = value(x0, y0 + 1) + (value(x0 + 1, y0 + 1) - value(x0, y0 + 1)) * tx;
+        top + (bottom - top) * ty
+    }
+}
+
+/// Blend between two colours, with `t` in `0..1`
+fn lerp_colour(a: [f64; 3], b: [f64; 3], t: f64) -> image::Rgb<u8> {
+    image::Rgb([0, 1, 2].map(|i| (a[i] + (b[i] - a[i]) * t).clamp(0., 255.) as u8))
+}
+
+/// Fractal noise coloured by height, from water through beaches and grass to rock and snow
+fn landscape(rng: &mut Pcg64, width: u32) -> image::RgbImage {
+    let octaves = (0..6)
+        .map(|octave| ValueNoise::new(rng, 4 << octave))
+        .collect::<Vec<_>>();
+    let palette: [(f64, [f64; 3]); 6] = [
+        (0.0, [20., 45., 90.]),
+        (0.45, [50., 100., 150.]),
+        (0.5, [200., 190., 140.]),
+        (0.55, [80., 130., 60.]),
+        (0.7, [60., 90., 45.]),
+        (0.85, [120., 110., 100.]),
+    ];
+    image::RgbImage::from_fn(width, width, |x, y| {
+        let (x, y) = (x as f64 / width as f64, y as f64 / width as f64);
+        let (mut height, mut amplitude, mut total) = (0., 1., 0.);
+        for noise in &octaves {
+            height += noise.at(x, y) * amplitude;
+            total += amplitude;
+            amplitude /= 2.;
+        }
+        let height = height / total;
+        let upper = palette
+            .iter()
+            .position(|(h, _)| *h > height)
+            .unwrap_or(palette.len())
+            .max(1);
+        if upper == palette.len() {
+            // snow on the highest peaks
+            return lerp_colour(
+                palette[upper - 1].1,
+                [240., 240., 245.],
+                (height - 0.85) / 0.15,
+            );
+        }
+        let ((h0, c0), (h1, c1)) = (palette[upper - 1], palette[upper]);
+        lerp_colour(c0, c1, (height - h0) / (h1 - h0))
+    })
+}
+
+/// A gradient between two random colours at a random angle, with a little low frequency variation
+fn gradient(rng: &mut Pcg64, width: u32) -> image::RgbImage {
+    let from = [0; 3].map(|_| rng.gen_range(0. ..255.));
+    let to = [0; 3].map(|_| rng.gen_range(0. ..255.));
+    let angle: f64 = rng.gen_range(0. ..std::f64::consts::TAU);
+    let (dx, dy) = (angle.cos(), angle.sin());
+    let noise = ValueNoise::new(rng, 8);
+    image::RgbImage::from_fn(width, width, |x, y| {
+        let (x, y) = (x as f64 / width as f64, y as f64 / width as f64);
+        // project onto the direction of the gradient, scaled into 0..1
+        let t = ((x - 0.5) * dx + (y - 0.5) * dy) / std::f64::consts::SQRT_2 + 0.5;
+        let t = t + (noise.at(x, y) - 0.5) * 0.1;
+        lerp_colour(from, to, t.clamp(0., 1.))
+    })
+}
+
+/// Overlapping sine waves of random frequency and phase, with each channel out of phase
+fn plasma(rng: &mut Pcg64, width: u32) -> image::RgbImage {
+    let waves = (0..4)
+        .map(|_| {
+            (
+                rng.gen_range(2. ..12.),
+                rng.gen_range(2. ..12.),
+                rng.gen_range(0. ..std::f64::consts::TAU),
+            )
+        })
+        .collect::<Vec<(f64, f64, f64)>>();
+    let shift = [0; 3].map(|_| rng.gen_range(0. ..std::f64::consts::TAU));
+    image::RgbImage::from_fn(width, width, |x, y| {
+        let (x, y) = (x as f64 / width as f64, y as f64 / width as f64);
+        let v: f64 = waves
+            .iter()
+            .map(|(fx, fy, phase)| (x * fx + y * fy + phase).sin())
+            .sum::<f64>()
+            / waves.len() as f64;
+        image::Rgb(shift.map(|s| ((v * std::f64::consts::PI + s).sin() * 127.5 + 127.5) as u8))
+    })
+}
+
+/// Width of the largest cover image requested to hide data in
+pub const MAX_SQUARE_IMAGE_WIDTH: usize = 5000;
+
+/// The most bytes to hide in a single cover image, so larger files are split across several.
+///
+/// This is the length [`get_square_image_width_from_bytes`] requests the widest cover image for.
+pub fn max_bytes_for_square_image() -> usize {
+    (MAX_SQUARE_IMAGE_WIDTH / 2).pow(2) * 3 / 8
 }
 
 pub fn get_square_image_width_from_bytes(length: usize) -> usize {
     let min = 200;
-    let max = 5000;
+    let max = MAX_SQUARE_IMAGE_WIDTH;
     let width = ((length * 8) as f64 / 3_f64).sqrt() as usize * 2;
     match width.cmp(&min) {
         std::cmp::Ordering::Less | std::cmp::Ordering::Equal => min,
@@ -70,20 +474,243 @@ pub fn get_square_image_width_from_bytes(length: usize) -> usize {
 #[cfg(test)]
 mod tests {
     use super::*;
+    #[cfg(feature = "net")]
     #[test]
     fn test_picsum_api_client_creation() {
         let _ = PicsumClient::new();
     }
 
+    /// A HTTP server on localhost replying to each connection with the next of the given responses after a delay,
+    /// returning the requests received once all responses are sent.
+    #[cfg(feature = "net")]
+    fn mock_server(
+        responses: Vec<(&'static str, &'static [u8], Duration)>,
+    ) -> (String, std::thread::JoinHandle<Vec<String>>) {
+        use std::io::{BufRead, BufReader, Write};
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let handle = std::thread::spawn(move || {
+            let handlers = responses
+                .into_iter()
+                .map(|(status, body, delay)| {
+                    let (mut stream, _) = listener.accept().unwrap();
+                    std::thread::spawn(move || {
+                        let mut reader = BufReader::new(stream.try_clone().unwrap());
+                        let mut request = String::new();
+                        loop {
+                            let mut line = String::new();
+                            if reader.read_line(&mut line).unwrap() == 0 || line == "\r\n" {
+                                break;
+                            }
+                            request.push_str(&line);
+                        }
+                        std::thread::sleep(delay);
+                        let _ = write!(
+                            stream,
+                            "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                            status,
+                            body.len()
+                        );
+                        let _ = stream.write_all(body);
+                        request
+                    })
+                })
+                .collect::<Vec<_>>();
+            handlers.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        (url, handle)
+    }
+
+    #[cfg(feature = "net")]
+    fn mock_config(base_url: String) -> ClientConfig {
+        ClientConfig {
+            base_url,
+            user_agent: "stegosaurust-test".to_string(),
+            timeout: Duration::from_secs(5),
+            retries: 2,
+            backoff: Duration::from_millis(10),
+            cache_dir: None,
+        }
+    }
+
+    #[cfg(feature = "net")]
     #[test]
     fn test_picsum_api_get_square_image() {
-        let client = PicsumClient::new();
-        let result = client.get_square_image(100);
-        assert!(result.is_ok());
+        let (url, server) = mock_server(vec![("200 OK", b"image", Duration::ZERO)]);
+        let client = PicsumClient::with_config(mock_config(url)).unwrap();
+        assert_eq!(client.get_square_image(100).unwrap(), b"image");
+        let requests = server.join().unwrap();
+        assert!(requests[0].starts_with("GET /100 HTTP/1.1"));
+        assert!(requests[0]
+            .to_lowercase()
+            .contains("user-agent: stegosaurust-test"));
+    }
+
+    #[cfg(feature = "net")]
+    #[test]
+    fn test_picsum_api_retries_server_errors() {
+        let (url, server) = mock_server(vec![
+            ("503 Service Unavailable", b"", Duration::ZERO),
+            ("429 Too Many Requests", b"", Duration::ZERO),
+            ("200 OK", b"image", Duration::ZERO),
+        ]);
+        let client = PicsumClient::with_config(mock_config(url)).unwrap();
+        assert_eq!(client.get_square_image(100).unwrap(), b"image");
+        assert_eq!(server.join().unwrap().len(), 3);
+    }
+
+    #[cfg(feature = "net")]
+    #[test]
+    fn test_picsum_api_gives_up() {
+        let (url, server) = mock_server(vec![
+            ("500 Internal Server Error", b"", Duration::ZERO),
+            ("500 Internal Server Error", b"broken", Duration::ZERO),
+        ]);
+        let mut config = mock_config(url);
+        config.retries = 1;
+        let client = PicsumClient::with_config(config).unwrap();
+        let err = client.get_square_image(100).unwrap_err();
+        assert_eq!(err.to_string(), "500:broken");
+        server.join().unwrap();
+    }
+
+    #[cfg(feature = "net")]
+    #[test]
+    fn test_picsum_api_client_errors_not_retried() {
+        let (url, server) = mock_server(vec![("404 Not Found", b"", Duration::ZERO)]);
+        let client = PicsumClient::with_config(mock_config(url)).unwrap();
+        assert!(client.get_square_image(100).is_err());
+        assert_eq!(server.join().unwrap().len(), 1);
+    }
+
+    #[cfg(feature = "net")]
+    #[test]
+    fn test_picsum_api_timeout() {
+        let (url, server) = mock_server(vec![
+            ("200 OK", b"slow", Duration::from_millis(500)),
+            ("200 OK", b"image", Duration::ZERO),
+        ]);
+        let mut config = mock_config(url);
+        config.timeout = Duration::from_millis(100);
+        let client = PicsumClient::with_config(config).unwrap();
+        assert_eq!(client.get_square_image(100).unwrap(), b"image");
+        server.join().unwrap();
+    }
+
+    #[cfg(feature = "net")]
+    #[test]
+    fn test_picsum_api_cache() {
+        let cache_dir =
+            std::env::temp_dir().join(format!("stegosaurust-cache-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&cache_dir);
+        let (url, server) = mock_server(vec![("200 OK", b"image", Duration::ZERO)]);
+        let mut config = mock_config(url);
+        config.cache_dir = Some(cache_dir.clone());
+        let client = PicsumClient::with_config(config).unwrap();
+        assert_eq!(client.get_square_image(100).unwrap(), b"image");
+        server.join().unwrap();
+        // only the image is left in the cache, with no temporary files
+        let cached = std::fs::read_dir(&cache_dir).unwrap().count();
+        assert_eq!(cached, 1);
+        // the server has stopped, so this can only come from the cache
+        assert_eq!(client.get_square_image(100).unwrap(), b"image");
+        assert!(client.get_square_image(200).is_err());
+        std::fs::remove_dir_all(cache_dir).unwrap();
     }
 
     #[test]
     fn test_get_square_image_width_from_bytes() {
         assert_eq!(get_square_image_width_from_bytes(100), 200)
     }
+
+    #[test]
+    fn test_max_bytes_for_square_image() {
+        let max = max_bytes_for_square_image();
+        assert_eq!(
+            get_square_image_width_from_bytes(max),
+            MAX_SQUARE_IMAGE_WIDTH
+        );
+        assert!(get_square_image_width_from_bytes(max - 10_000) < MAX_SQUARE_IMAGE_WIDTH);
+    }
+
+    #[test]
+    fn test_generated_images_reproducible() {
+        for kind in [CoverKind::Landscape, CoverKind::Gradient, CoverKind::Plasma] {
+            let first = GeneratedImages::new(kind, "seed");
+            let second = GeneratedImages::new(kind, "seed");
+            let img = first.generate("a.txt", 64);
+            assert_eq!(img.dimensions(), (64, 64));
+            // the same whatever was generated before it
+            second.generate("b.txt", 64);
+            assert_eq!(img, second.generate("a.txt", 64));
+            // each file gets a different image
+            assert_ne!(first.generate("b.txt", 64), img);
+        }
+    }
+
+    #[test]
+    fn test_generated_images_png() {
+        let images = GeneratedImages::new(CoverKind::Landscape, "seed");
+        let bytes = images.get_square_image(50).unwrap();
+        assert_eq!(dimensions(&bytes), (50, 50));
+    }
+
+    fn covers_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("stegosaurust-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        for (fname, width, height) in [
+            ("small.png", 100, 100),
+            ("wide.png", 400, 250),
+            ("large.png", 600, 600),
+        ] {
+            image::RgbImage::new(width, height)
+                .save(dir.join(fname))
+                .unwrap();
+        }
+        std::fs::write(dir.join("notes.txt"), "not an image").unwrap();
+        dir
+    }
+
+    fn dimensions(bytes: &[u8]) -> (u32, u32) {
+        let img = image::load_from_memory(bytes).unwrap();
+        (img.width(), img.height())
+    }
+
+    #[test]
+    fn test_local_images_best_fit() {
+        let dir = covers_dir("best-fit");
+        let covers = LocalImages::new(&dir, CoverSelection::BestFit, CoverFit::None).unwrap();
+        assert_eq!(covers.covers.len(), 3);
+        // the wide cover has the capacity of a 300x300 image without being square
+        assert_eq!(
+            dimensions(&covers.get_square_image(300).unwrap()),
+            (400, 250)
+        );
+        assert!(covers.get_square_image(1000).is_err());
+
+        let covers = LocalImages::new(&dir, CoverSelection::BestFit, CoverFit::Crop).unwrap();
+        assert_eq!(
+            dimensions(&covers.get_square_image(300).unwrap()),
+            (300, 300)
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_local_images_resize() {
+        let dir = covers_dir("resize");
+        let covers = LocalImages::new(&dir, CoverSelection::Random, CoverFit::Resize).unwrap();
+        assert_eq!(
+            dimensions(&covers.get_square_image(200).unwrap()),
+            (200, 200)
+        );
+        // scaled up from the largest cover when none are large enough
+        assert_eq!(
+            dimensions(&covers.get_square_image(800).unwrap()),
+            (800, 800)
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
 }
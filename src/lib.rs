@@ -1,47 +1,124 @@
 //! Stegosaurust is a command line program and library to perform steganography in rust.
 
 /// Data structures for parsing and validating command line options to `stegosaurust`.
+#[cfg(not(target_arch = "wasm32"))]
 pub mod cli;
 
+/// Structured results of running each subcommand, printed as JSON with `--format json`.
+#[cfg(not(target_arch = "wasm32"))]
+mod report;
+#[cfg(not(target_arch = "wasm32"))]
 mod run;
+#[cfg(not(target_arch = "wasm32"))]
 pub use run::run;
 
+/// Analysis module to plan how much data can be hidden in images, and estimate how detectable it is.
+pub mod analysis;
+/// Archive module to pack several files into a single payload.
+pub mod archive;
 /// Compression module with a simple interface to compression/decomporession functions, without all the fuss.
 pub mod compress;
 /// Cryptography module to provide a simple interface to
 /// common encryption and hashing function, without all the fuss.
 pub mod crypto;
+/// Manifest module recording the files disguised in a directory, to verify them when restored.
+pub mod manifest;
+/// Payload module describing the data hidden in an image, optionally with metadata about the file it came from.
+pub mod payload;
 /// Steganography module containing different implementations of encoding methods.
 pub mod steganography;
+/// Stego module with a builder API to hide payloads in images and reveal them, compressing, encrypting and encoding them on the way.
+pub mod stego;
+/// Stream module to hide bytes in an image as they are written, and reveal them as they are read, with `std::io`.
+pub mod stream;
 
-/// Image API contains an HTTP client to fetch images from a remote API
+/// C ABI to hide and reveal payloads in the raw buffers of RGB images, declared in `include/stegosaurust.h`.
+#[cfg(feature = "ffi")]
+pub mod ffi;
+
+/// Python bindings to the steganography methods, compression, cryptography and analysis, working on numpy arrays.
+#[cfg(feature = "python")]
+pub mod python;
+
+/// Image API contains an HTTP client to fetch images from a remote API, and offline sources of cover images
+#[cfg(not(target_arch = "wasm32"))]
 pub mod image_api;
 
+/// JavaScript bindings to hide and reveal messages in PNG images from WebAssembly.
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
 use thiserror::Error;
 #[derive(Error, Debug, Eq, PartialEq)]
 pub enum StegError {
     #[error("Encoded message not found in data")]
     EncodingNotFound,
+    #[error("Error encoding message: `{0}`")]
+    Encoding(String),
     #[error("Error decoding message: `{0}`")]
     Decoding(String),
     #[error("Compression error")]
     Compression(#[from] CompressionError),
     #[error("Encryption error")]
     Crypto(#[from] CryptoError),
+    #[error("Verification failed: `{0}`")]
+    Verification(String),
+    #[error("Base64 decoding error")]
+    Base64(#[from] base64::DecodeError),
+    #[error("Invalid configuration: `{0}`")]
+    InvalidConfig(String),
     #[error("Unknown steganography error")]
     Unknown,
 }
 
+impl From<steganography::EmbedError> for StegError {
+    fn from(err: steganography::EmbedError) -> Self {
+        match err {
+            steganography::EmbedError::EncodingNotFound => StegError::EncodingNotFound,
+            steganography::EmbedError::Encoding(err) => StegError::Encoding(err),
+            steganography::EmbedError::InvalidConfig(err) => StegError::InvalidConfig(err),
+        }
+    }
+}
+
+impl StegError {
+    /// Stable code identifying the kind of error, for machine-readable output.
+    pub fn code(&self) -> &'static str {
+        match self {
+            StegError::EncodingNotFound => "STEG_ENCODING_NOT_FOUND",
+            StegError::Encoding(_) => "STEG_ENCODING",
+            StegError::Decoding(_) => "STEG_DECODING",
+            StegError::Compression(err) => err.code(),
+            StegError::Crypto(err) => err.code(),
+            StegError::Verification(_) => "STEG_VERIFICATION",
+            StegError::Base64(_) => "STEG_BASE64",
+            StegError::InvalidConfig(_) => "STEG_INVALID_CONFIG",
+            StegError::Unknown => "STEG_UNKNOWN",
+        }
+    }
+}
+
 #[derive(Error, Debug, Eq, PartialEq)]
 pub enum CompressionError {
-    #[error("Compression error")]
-    Compression(#[from] compression::prelude::CompressionError),
-    #[error("Decompression error")]
-    Decompression(#[from] compression::prelude::BZip2Error),
+    #[error("Compression error: {0}")]
+    Compression(String),
+    #[error("Decompression error: {0}")]
+    Decompression(String),
     #[error("empty data")]
     EmptyData,
 }
 
+impl CompressionError {
+    /// Stable code identifying the kind of error, for machine-readable output.
+    pub fn code(&self) -> &'static str {
+        match self {
+            CompressionError::Compression(_) => "COMPRESSION_COMPRESSION",
+            CompressionError::Decompression(_) => "COMPRESSION_DECOMPRESSION",
+            CompressionError::EmptyData => "COMPRESSION_EMPTY_DATA",
+        }
+    }
+}
+
 #[derive(Error, Debug, Eq, PartialEq)]
 pub enum CryptoError {
     #[error("Failed to get random salt")]
@@ -55,3 +132,16 @@ pub enum CryptoError {
     #[error("unknown cryptography error")]
     Unknown,
 }
+
+impl CryptoError {
+    /// Stable code identifying the kind of error, for machine-readable output.
+    pub fn code(&self) -> &'static str {
+        match self {
+            CryptoError::Salt => "CRYPTO_SALT",
+            CryptoError::PasswordHash => "CRYPTO_PASSWORD_HASH",
+            CryptoError::Cipher(_) => "CRYPTO_CIPHER",
+            CryptoError::Decryption(_) => "CRYPTO_DECRYPTION",
+            CryptoError::Unknown => "CRYPTO_UNKNOWN",
+        }
+    }
+}
@@ -1,13 +1,25 @@
+#[cfg(not(target_arch = "wasm32"))]
 use stegosaurust::{cli, run};
+#[cfg(not(target_arch = "wasm32"))]
 use structopt::StructOpt;
 
+#[cfg(not(target_arch = "wasm32"))]
 extern crate env_logger;
 
+#[cfg(not(target_arch = "wasm32"))]
 fn main() {
     env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
     let opt = cli::Opt::from_args();
+    let format = opt.format;
     if let Err(e) = run(opt) {
-        eprintln!("{:?}", e);
+        // with `--format json` the error has already been printed as part of the report
+        if format == cli::Format::Text {
+            eprintln!("{:?}", e);
+        }
         std::process::exit(1);
     }
 }
+
+/// The command line program reads and writes files, so is not built for WebAssembly; see the `wasm` feature instead.
+#[cfg(target_arch = "wasm32")]
+fn main() {}
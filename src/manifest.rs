@@ -0,0 +1,166 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::crypto;
+use crate::payload::ChunkInfo;
+use crate::StegError;
+
+/// Name of the manifest file written to a disguised directory, hidden so it is not disguised itself.
+pub const MANIFEST_NAME: &str = ".stegosaurust-manifest";
+/// Version of the manifest format written by this version of the program.
+const VERSION: u32 = 1;
+
+/// A file disguised as an image, recorded so it can be verified when restored.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// Name of the image the file is hidden in, relative to the disguised directory
+    pub carrier: String,
+    /// Path of the original file, relative to the disguised directory
+    pub path: String,
+    /// Size of the original file, or the part of it hidden in the image, in bytes
+    pub size: u64,
+    /// Hex encoded SHA-256 digest of the original file, or the part of it hidden in the image
+    pub sha256: String,
+    /// Where the data belongs in the original file, when it was split across several images
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub part: Option<ChunkInfo>,
+}
+
+impl ManifestEntry {
+    pub fn new(carrier: String, path: String, data: &[u8]) -> Self {
+        ManifestEntry {
+            carrier,
+            path,
+            size: data.len() as u64,
+            sha256: sha256_hex(data),
+            part: None,
+        }
+    }
+
+    /// Mark the entry as one of the parts of a file split across several images, created from the data of that part.
+    pub fn with_part(mut self, part: ChunkInfo) -> Self {
+        self.part = Some(part);
+        self
+    }
+
+    /// Check restored data is the original file.
+    pub fn verify(&self, data: &[u8]) -> Result<(), StegError> {
+        if data.len() as u64 != self.size {
+            return Err(StegError::Verification(format!(
+                "{} is {} bytes, expected {}",
+                self.path,
+                data.len(),
+                self.size
+            )));
+        }
+        if sha256_hex(data) != self.sha256 {
+            return Err(StegError::Verification(format!(
+                "{} does not match its SHA-256 digest",
+                self.path
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Maps each image in a disguised directory to the file hidden in it.
+///
+/// The manifest is stored encrypted with the key used to disguise the files, so it does not reveal their names.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    version: u32,
+    pub entries: Vec<ManifestEntry>,
+}
+
+impl Manifest {
+    pub fn new() -> Self {
+        Manifest {
+            version: VERSION,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Path of the manifest for a disguised directory
+    pub fn path(dir: &Path) -> PathBuf {
+        dir.join(MANIFEST_NAME)
+    }
+
+    /// Serialize and encrypt the manifest with `key`.
+    pub fn encrypt(&self, key: &[u8]) -> Result<Vec<u8>, StegError> {
+        let json = serde_json::to_vec(self).map_err(|e| StegError::Decoding(e.to_string()))?;
+        Ok(crypto::encrypt(&json, key)?)
+    }
+
+    /// Decrypt and parse a manifest encrypted with `key`.
+    pub fn decrypt(bytes: &[u8], key: &[u8]) -> Result<Self, StegError> {
+        let json = crypto::decrypt(bytes, key)?;
+        let manifest: Manifest = serde_json::from_slice(&json)
+            .map_err(|e| StegError::Decoding(format!("reading manifest: {}", e)))?;
+        if manifest.version > VERSION {
+            return Err(StegError::Decoding(format!(
+                "unsupported manifest version {}",
+                manifest.version
+            )));
+        }
+        Ok(manifest)
+    }
+
+    /// Record an entry, replacing any earlier entry for the same carrier.
+    pub fn insert(&mut self, entry: ManifestEntry) {
+        self.entries.retain(|e| e.carrier != entry.carrier);
+        self.entries.push(entry);
+    }
+}
+
+impl std::default::Default for Manifest {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manifest_roundtrip() {
+        let mut manifest = Manifest::new();
+        manifest.insert(ManifestEntry::new(
+            "a.png".to_string(),
+            "a.txt".to_string(),
+            b"aaa",
+        ));
+        manifest.insert(ManifestEntry::new(
+            "a.png".to_string(),
+            "b.txt".to_string(),
+            b"bbb",
+        ));
+        assert_eq!(manifest.entries.len(), 1);
+        let bytes = manifest.encrypt(b"key").unwrap();
+        assert_eq!(Manifest::decrypt(&bytes, b"key").unwrap(), manifest);
+        assert!(Manifest::decrypt(&bytes, b"wrong").is_err());
+    }
+
+    #[test]
+    fn test_manifest_entry_verify() {
+        let entry = ManifestEntry::new("a.png".to_string(), "a.txt".to_string(), b"hello");
+        assert!(entry.verify(b"hello").is_ok());
+        assert!(matches!(
+            entry.verify(b"hello!"),
+            Err(StegError::Verification(_))
+        ));
+        assert!(matches!(
+            entry.verify(b"jello"),
+            Err(StegError::Verification(_))
+        ));
+    }
+}
@@ -0,0 +1,552 @@
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::archive::Archive;
+use crate::StegError;
+
+/// Marks the start of a payload that carries more than just the raw message.
+const MAGIC: &[u8] = b"\x89STG";
+/// Version of the payload format written by this version of the program.
+const VERSION: u8 = 1;
+
+/// Kinds of structured payload that can follow the `MAGIC` header.
+#[derive(Clone, Copy)]
+#[repr(u8)]
+enum Kind {
+    File = 1,
+    Archive = 2,
+    Chunk = 3,
+}
+
+const HAS_MODE: u8 = 0b0000_0001;
+const HAS_MODIFIED: u8 = 0b0000_0010;
+const HAS_CONTENT_TYPE: u8 = 0b0000_0100;
+
+/// Information about a file hidden in an image, so it can be restored as it was.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileMetadata {
+    /// Original name of the file
+    pub name: String,
+    /// Unix permission bits of the file, without the setuid, setgid and sticky bits
+    pub mode: Option<u32>,
+    /// Modification time of the file, in seconds since the unix epoch
+    pub modified: Option<u64>,
+    /// Detected MIME type of the file contents
+    pub content_type: Option<String>,
+}
+
+impl FileMetadata {
+    /// Gather metadata about the file at `path`, where `data` is the contents of the file.
+    pub fn from_path(path: &Path, data: &[u8]) -> std::io::Result<Self> {
+        let meta = std::fs::metadata(path)?;
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        #[cfg(unix)]
+        let mode = {
+            use std::os::unix::fs::PermissionsExt;
+            Some(meta.permissions().mode() & 0o777)
+        };
+        #[cfg(not(unix))]
+        let mode = None;
+
+        let modified = meta
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_secs());
+
+        Ok(FileMetadata {
+            name,
+            mode,
+            modified,
+            content_type: Some(content_type(data).to_string()),
+        })
+    }
+
+    /// The name to restore the file as, stripped of any directory components.
+    pub fn file_name(&self) -> Option<&std::ffi::OsStr> {
+        Path::new(&self.name).file_name()
+    }
+
+    /// Apply the permissions and modification time to the file at `path`.
+    ///
+    /// Only the permission bits are applied, so a hidden file can never be restored setuid or setgid.
+    pub fn apply(&self, path: &Path) -> std::io::Result<()> {
+        #[cfg(unix)]
+        if let Some(mode) = self.mode {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode & 0o777))?;
+        }
+        if let Some(modified) = self.modified {
+            let mtime = filetime::FileTime::from_system_time(
+                SystemTime::UNIX_EPOCH + Duration::from_secs(modified),
+            );
+            filetime::set_file_mtime(path, mtime)?;
+        }
+        Ok(())
+    }
+
+    fn write(&self, out: &mut Vec<u8>) {
+        write_bytes(out, self.name.as_bytes());
+        let mut flags = 0;
+        if self.mode.is_some() {
+            flags |= HAS_MODE;
+        }
+        if self.modified.is_some() {
+            flags |= HAS_MODIFIED;
+        }
+        if self.content_type.is_some() {
+            flags |= HAS_CONTENT_TYPE;
+        }
+        out.push(flags);
+        if let Some(mode) = self.mode {
+            write_varint(out, mode as u64);
+        }
+        if let Some(modified) = self.modified {
+            write_varint(out, modified);
+        }
+        if let Some(content_type) = &self.content_type {
+            write_bytes(out, content_type.as_bytes());
+        }
+    }
+
+    fn read(reader: &mut Reader) -> Result<Self, StegError> {
+        let name = reader.string()?;
+        let flags = reader.u8()?;
+        let mode = if flags & HAS_MODE > 0 {
+            Some(reader.varint()? as u32)
+        } else {
+            None
+        };
+        let modified = if flags & HAS_MODIFIED > 0 {
+            Some(reader.varint()?)
+        } else {
+            None
+        };
+        let content_type = if flags & HAS_CONTENT_TYPE > 0 {
+            Some(reader.string()?)
+        } else {
+            None
+        };
+        Ok(FileMetadata {
+            name,
+            mode,
+            modified,
+            content_type,
+        })
+    }
+}
+
+/// Where a chunk of a file split across several images belongs in the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkInfo {
+    /// Identifies the chunks of the same file
+    pub group: u64,
+    /// Position of the chunk in the file, counting from zero
+    pub index: u32,
+    /// Number of chunks the file was split into
+    pub total: u32,
+}
+
+impl ChunkInfo {
+    fn write(&self, out: &mut Vec<u8>) {
+        write_varint(out, self.group);
+        write_varint(out, self.index as u64);
+        write_varint(out, self.total as u64);
+    }
+
+    fn read(reader: &mut Reader) -> Result<Self, StegError> {
+        let group = reader.varint()?;
+        let index = reader.varint()?;
+        let total = reader.varint()?;
+        if index >= total || total > u32::MAX as u64 {
+            return Err(StegError::Decoding(format!(
+                "invalid chunk {} of {}",
+                index, total
+            )));
+        }
+        Ok(ChunkInfo {
+            group,
+            index: index as u32,
+            total: total as u32,
+        })
+    }
+}
+
+/// Detect the MIME type of some data from its contents.
+///
+/// # Example
+/// ```rust
+/// use stegosaurust::payload::content_type;
+/// assert_eq!(content_type(b"hello"), "text/plain");
+/// assert_eq!(content_type(b"\x89PNG\r\n\x1a\n"), "image/png");
+/// ```
+pub fn content_type(data: &[u8]) -> &'static str {
+    match infer::get(data) {
+        Some(kind) => kind.mime_type(),
+        None if std::str::from_utf8(data).is_ok() => "text/plain",
+        None => "application/octet-stream",
+    }
+}
+
+/// The data hidden in an image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Payload {
+    /// Raw bytes with no additional information, as written by earlier versions
+    Raw(Vec<u8>),
+    /// The contents of a single file along with its metadata
+    File {
+        metadata: FileMetadata,
+        data: Vec<u8>,
+    },
+    /// Several files packed into an archive
+    Archive(Archive),
+    /// Part of a file too large to hide in a single image
+    Chunk {
+        metadata: FileMetadata,
+        chunk: ChunkInfo,
+        data: Vec<u8>,
+    },
+}
+
+impl Payload {
+    /// Serialize the payload into bytes ready to be hidden.
+    ///
+    /// Raw payloads are written as is so they stay readable by earlier versions.
+    pub fn into_bytes(self) -> Vec<u8> {
+        match self {
+            Payload::Raw(data) => data,
+            Payload::File { metadata, data } => {
+                let mut out = header(Kind::File);
+                metadata.write(&mut out);
+                out.extend_from_slice(&data);
+                out
+            }
+            Payload::Archive(archive) => {
+                let mut out = header(Kind::Archive);
+                archive.write(&mut out);
+                out
+            }
+            Payload::Chunk {
+                metadata,
+                chunk,
+                data,
+            } => {
+                let mut out = header(Kind::Chunk);
+                chunk.write(&mut out);
+                metadata.write(&mut out);
+                out.extend_from_slice(&data);
+                out
+            }
+        }
+    }
+
+    /// Split a file into chunks of at most `size` bytes, each to be hidden in its own image.
+    ///
+    /// # Example
+    /// ```rust
+    /// use stegosaurust::payload::{FileMetadata, Payload};
+    /// let metadata = FileMetadata {
+    ///     name: "notes.txt".to_string(),
+    ///     mode: None,
+    ///     modified: None,
+    ///     content_type: None,
+    /// };
+    /// let chunks = Payload::chunks(&metadata, b"hello world", 5, 1);
+    /// assert_eq!(chunks.len(), 3);
+    /// assert_eq!(chunks[2].size(), 1);
+    /// ```
+    pub fn chunks(metadata: &FileMetadata, data: &[u8], size: usize, group: u64) -> Vec<Payload> {
+        let total = data.len().div_ceil(size).max(1) as u32;
+        data.chunks(size.max(1))
+            .enumerate()
+            .map(|(index, data)| Payload::Chunk {
+                metadata: metadata.clone(),
+                chunk: ChunkInfo {
+                    group,
+                    index: index as u32,
+                    total,
+                },
+                data: data.to_vec(),
+            })
+            .collect()
+    }
+
+    /// Parse a payload from the bytes revealed from an image.
+    ///
+    /// Anything not starting with the payload header is treated as a raw message.
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, StegError> {
+        if !bytes.starts_with(MAGIC) {
+            return Ok(Payload::Raw(bytes));
+        }
+        let mut reader = Reader::new(&bytes[MAGIC.len()..]);
+        let version = reader.u8()?;
+        if version > VERSION {
+            return Err(StegError::Decoding(format!(
+                "unsupported payload version {}",
+                version
+            )));
+        }
+        match reader.u8()? {
+            k if k == Kind::File as u8 => {
+                let metadata = FileMetadata::read(&mut reader)?;
+                let data = reader.rest().to_vec();
+                Ok(Payload::File { metadata, data })
+            }
+            k if k == Kind::Archive as u8 => Ok(Payload::Archive(Archive::read(&mut reader)?)),
+            k if k == Kind::Chunk as u8 => {
+                let chunk = ChunkInfo::read(&mut reader)?;
+                let metadata = FileMetadata::read(&mut reader)?;
+                let data = reader.rest().to_vec();
+                Ok(Payload::Chunk {
+                    metadata,
+                    chunk,
+                    data,
+                })
+            }
+            other => Err(StegError::Decoding(format!(
+                "unknown payload kind {}",
+                other
+            ))),
+        }
+    }
+
+    /// Metadata about the hidden file, if there is any
+    pub fn metadata(&self) -> Option<&FileMetadata> {
+        match self {
+            Payload::File { metadata, .. } | Payload::Chunk { metadata, .. } => Some(metadata),
+            Payload::Raw(_) | Payload::Archive(_) => None,
+        }
+    }
+
+    /// Where the hidden data belongs in a file split across several images, if it does
+    pub fn chunk(&self) -> Option<&ChunkInfo> {
+        match self {
+            Payload::Chunk { chunk, .. } => Some(chunk),
+            Payload::Raw(_) | Payload::File { .. } | Payload::Archive(_) => None,
+        }
+    }
+
+    /// Size of the hidden data, without any metadata
+    pub fn size(&self) -> usize {
+        match self {
+            Payload::Raw(data) | Payload::File { data, .. } | Payload::Chunk { data, .. } => {
+                data.len()
+            }
+            Payload::Archive(archive) => archive.size(),
+        }
+    }
+}
+
+fn header(kind: Kind) -> Vec<u8> {
+    let mut out = MAGIC.to_vec();
+    out.push(VERSION);
+    out.push(kind as u8);
+    out
+}
+
+/// Write a LEB128 variable length integer.
+pub(crate) fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+/// Write length-prefixed bytes.
+pub(crate) fn write_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    write_varint(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+/// Reads the fields of a payload, failing on truncated data rather than panicking.
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Self {
+        Reader { bytes, pos: 0 }
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, StegError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(crate) fn varint(&mut self) -> Result<u64, StegError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.u8()?;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(StegError::Decoding("malformed payload integer".to_string()))
+    }
+
+    pub(crate) fn bytes(&mut self) -> Result<&'a [u8], StegError> {
+        let len = self.varint()? as usize;
+        self.take(len)
+    }
+
+    pub(crate) fn string(&mut self) -> Result<String, StegError> {
+        let bytes = self.bytes()?;
+        String::from_utf8(bytes.to_vec())
+            .map_err(|e| StegError::Decoding(format!("reading payload text: {}", e)))
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], StegError> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or_else(|| StegError::Decoding("payload is truncated".to_string()))?;
+        let taken = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(taken)
+    }
+
+    fn rest(&mut self) -> &'a [u8] {
+        let rest = &self.bytes[self.pos..];
+        self.pos = self.bytes.len();
+        rest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata() -> FileMetadata {
+        FileMetadata {
+            name: "report.pdf".to_string(),
+            mode: Some(0o644),
+            modified: Some(1_700_000_000),
+            content_type: Some("application/pdf".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_raw_payload_unchanged() {
+        let payload = Payload::Raw(b"secret message".to_vec());
+        assert_eq!(payload.into_bytes(), b"secret message");
+    }
+
+    #[test]
+    fn test_file_payload_roundtrip() {
+        let payload = Payload::File {
+            metadata: metadata(),
+            data: b"%PDF-1.4".to_vec(),
+        };
+        let bytes = payload.clone().into_bytes();
+        assert_eq!(Payload::from_bytes(bytes).unwrap(), payload);
+    }
+
+    #[test]
+    fn test_file_payload_without_optional_metadata() {
+        let payload = Payload::File {
+            metadata: FileMetadata {
+                name: "notes.txt".to_string(),
+                mode: None,
+                modified: None,
+                content_type: None,
+            },
+            data: Vec::new(),
+        };
+        let bytes = payload.clone().into_bytes();
+        assert_eq!(Payload::from_bytes(bytes).unwrap(), payload);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_apply_drops_setuid_and_setgid() {
+        use std::os::unix::fs::PermissionsExt;
+        let path = std::env::temp_dir().join(format!("stegosaurust-apply-{}", std::process::id()));
+        std::fs::write(&path, b"#!/bin/sh").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o6755)).unwrap();
+        let stored = FileMetadata::from_path(&path, b"#!/bin/sh").unwrap();
+        assert_eq!(stored.mode, Some(0o755));
+
+        let metadata = FileMetadata {
+            mode: Some(0o6755),
+            ..metadata()
+        };
+        metadata.apply(&path).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(mode & 0o7777, 0o755);
+    }
+
+    #[test]
+    fn test_chunk_payloads_roundtrip() {
+        let data = (0..=255).collect::<Vec<u8>>();
+        let chunks = Payload::chunks(&metadata(), &data, 100, 42);
+        assert_eq!(chunks.len(), 3);
+        let mut joined = Vec::new();
+        for (i, payload) in chunks.into_iter().enumerate() {
+            let payload = Payload::from_bytes(payload.into_bytes()).unwrap();
+            assert_eq!(payload.metadata(), Some(&metadata()));
+            assert_eq!(
+                payload.chunk(),
+                Some(&ChunkInfo {
+                    group: 42,
+                    index: i as u32,
+                    total: 3
+                })
+            );
+            if let Payload::Chunk { data, .. } = payload {
+                joined.extend(data);
+            }
+        }
+        assert_eq!(joined, data);
+    }
+
+    #[test]
+    fn test_invalid_chunk_errors() {
+        let mut bytes = header(Kind::Chunk);
+        ChunkInfo {
+            group: 1,
+            index: 3,
+            total: 3,
+        }
+        .write(&mut bytes);
+        metadata().write(&mut bytes);
+        assert!(matches!(
+            Payload::from_bytes(bytes),
+            Err(StegError::Decoding(_))
+        ));
+    }
+
+    #[test]
+    fn test_truncated_payload_errors() {
+        let mut bytes = Payload::File {
+            metadata: metadata(),
+            data: Vec::new(),
+        }
+        .into_bytes();
+        bytes.truncate(10);
+        assert!(matches!(
+            Payload::from_bytes(bytes),
+            Err(StegError::Decoding(_))
+        ));
+    }
+
+    #[test]
+    fn test_file_name_strips_directories() {
+        let mut meta = metadata();
+        meta.name = "../../etc/passwd".to_string();
+        assert_eq!(meta.file_name().unwrap(), "passwd");
+    }
+}
@@ -0,0 +1,303 @@
+use std::str::FromStr;
+
+use image::RgbImage;
+use numpy::ndarray::Array3;
+use numpy::{IntoPyArray, PyArray3, PyReadonlyArray3};
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+use crate::analysis;
+use crate::steganography::{
+    BitDistribution, BitEncoder, EmbedError, Lsb, Rsb, StegMethod, Steganography,
+};
+
+create_exception!(
+    stegosaurust,
+    StegError,
+    PyException,
+    "Failed to hide or reveal data, with the message and stable code (e.g. `STEG_ENCODING_NOT_FOUND`) as its args."
+);
+create_exception!(
+    stegosaurust,
+    CompressionError,
+    StegError,
+    "Failed to compress or decompress data."
+);
+create_exception!(
+    stegosaurust,
+    CryptoError,
+    StegError,
+    "Failed to encrypt or decrypt data."
+);
+
+impl From<crate::CompressionError> for PyErr {
+    fn from(err: crate::CompressionError) -> Self {
+        CompressionError::new_err((err.to_string(), err.code()))
+    }
+}
+
+impl From<crate::CryptoError> for PyErr {
+    fn from(err: crate::CryptoError) -> Self {
+        CryptoError::new_err((err.to_string(), err.code()))
+    }
+}
+
+impl From<crate::StegError> for PyErr {
+    fn from(err: crate::StegError) -> Self {
+        match err {
+            crate::StegError::Compression(err) => err.into(),
+            crate::StegError::Crypto(err) => err.into(),
+            err => StegError::new_err((err.to_string(), err.code())),
+        }
+    }
+}
+
+/// Parse a method or distribution, raising `StegError` if it is not valid
+fn parse<T: FromStr<Err = EmbedError>>(s: &str) -> PyResult<T> {
+    Ok(s.parse().map_err(crate::StegError::from)?)
+}
+
+/// Copy an array of shape `(height, width, 3)`, as read by Pillow or OpenCV, into an image
+fn to_image(array: PyReadonlyArray3<'_, u8>) -> PyResult<RgbImage> {
+    let array = array.as_array();
+    let (height, width, channels) = array.dim();
+    if channels != 3 {
+        return Err(PyValueError::new_err(format!(
+            "expected an RGB image of shape (height, width, 3), got {:?}",
+            array.shape()
+        )));
+    }
+    let raw = array.iter().copied().collect();
+    Ok(RgbImage::from_raw(width as u32, height as u32, raw).expect("buffer matches dimensions"))
+}
+
+/// Move an image into an array of shape `(height, width, 3)`
+fn to_array(py: Python<'_>, image: RgbImage) -> Bound<'_, PyArray3<u8>> {
+    let (width, height) = image.dimensions();
+    Array3::from_shape_vec((height as usize, width as usize, 3), image.into_raw())
+        .expect("buffer matches dimensions")
+        .into_pyarray(py)
+}
+
+/// A steganography method hiding messages in images, as uint8 arrays of shape `(height, width, 3)`.
+#[pyclass(subclass, name = "Steganography", module = "stegosaurust")]
+pub struct PySteganography {
+    encoder: BitEncoder,
+}
+
+#[pymethods]
+impl PySteganography {
+    /// Hide a message in a copy of an image
+    fn encode<'py>(
+        &mut self,
+        py: Python<'py>,
+        image: PyReadonlyArray3<'py, u8>,
+        message: &[u8],
+    ) -> PyResult<Bound<'py, PyArray3<u8>>> {
+        let image = to_image(image)?;
+        let encoded = py.detach(|| self.encoder.encode(&image, message))?;
+        Ok(to_array(py, encoded))
+    }
+
+    /// Reveal the message hidden in an image
+    fn decode<'py>(
+        &mut self,
+        py: Python<'py>,
+        image: PyReadonlyArray3<'py, u8>,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        let image = to_image(image)?;
+        let message = py.detach(|| self.encoder.decode(&image))?;
+        Ok(PyBytes::new(py, &message))
+    }
+
+    /// Maximum length of a message that can be hidden in an image
+    fn max_len(&self, image: PyReadonlyArray3<'_, u8>) -> PyResult<usize> {
+        Ok(self.encoder.max_len(&to_image(image)?))
+    }
+}
+
+/// Least significant bit encoding, distributing bits `sequential`ly or `linear`ly, with `linear-N` to decode.
+#[pyclass(extends = PySteganography, name = "Lsb", module = "stegosaurust")]
+pub struct PyLsb;
+
+#[pymethods]
+impl PyLsb {
+    #[new]
+    #[pyo3(signature = (distribution = "sequential"))]
+    fn new(distribution: &str) -> PyResult<(Self, PySteganography)> {
+        let encoder = BitEncoder::new(Box::<Lsb>::default(), Some(parse(distribution)?));
+        Ok((PyLsb, PySteganography { encoder }))
+    }
+}
+
+/// Random significant bit encoding, hiding bits in one of the `max_bit` least significant bits chosen by `seed`.
+#[pyclass(extends = PySteganography, name = "Rsb", module = "stegosaurust")]
+pub struct PyRsb;
+
+#[pymethods]
+impl PyRsb {
+    #[new]
+    #[pyo3(signature = (seed, max_bit, distribution = "sequential"))]
+    fn new(seed: &str, max_bit: u8, distribution: &str) -> PyResult<(Self, PySteganography)> {
+        let rsb = Rsb::new(max_bit, seed).map_err(crate::StegError::from)?;
+        let encoder = BitEncoder::new(Box::new(rsb), Some(parse(distribution)?));
+        Ok((PyRsb, PySteganography { encoder }))
+    }
+}
+
+/// Compress data with bzip2
+#[pyfunction]
+fn compress<'py>(py: Python<'py>, data: &[u8]) -> PyResult<Bound<'py, PyBytes>> {
+    Ok(PyBytes::new(py, &crate::compress::compress(data)?))
+}
+
+/// Decompress data compressed with bzip2
+#[pyfunction]
+fn decompress<'py>(py: Python<'py>, data: &[u8]) -> PyResult<Bound<'py, PyBytes>> {
+    Ok(PyBytes::new(py, &crate::compress::decompress(data)?))
+}
+
+/// Encrypt data with AES-256-CBC, compatible with `openssl enc -aes-256-cbc -pbkdf2`
+#[pyfunction]
+fn encrypt<'py>(py: Python<'py>, data: &[u8], key: &[u8]) -> PyResult<Bound<'py, PyBytes>> {
+    Ok(PyBytes::new(py, &crate::crypto::encrypt(data, key)?))
+}
+
+/// Decrypt data encrypted with `encrypt`
+#[pyfunction]
+fn decrypt<'py>(py: Python<'py>, data: &[u8], key: &[u8]) -> PyResult<Bound<'py, PyBytes>> {
+    Ok(PyBytes::new(py, &crate::crypto::decrypt(data, key)?))
+}
+
+/// Number of bytes that can be hidden in an image of the given dimensions
+#[pyfunction]
+fn capacity(width: u32, height: u32) -> usize {
+    analysis::capacity(width, height)
+}
+
+/// Smallest width of a square image that can hide `length` bytes
+#[pyfunction]
+fn min_square_width(length: usize) -> u32 {
+    analysis::min_square_width(length)
+}
+
+/// Length of a message once encrypted with `encrypt`
+#[pyfunction]
+fn encrypted_len(length: usize) -> usize {
+    analysis::encrypted_len(length)
+}
+
+/// Length of a message once base64 encoded
+#[pyfunction]
+fn base64_len(length: usize) -> usize {
+    analysis::base64_len(length)
+}
+
+/// Length of the linear distribution a message of `length` bytes is spread over, needed to decode it
+#[pyfunction]
+fn linear_length(length: usize) -> usize {
+    crate::steganography::linear_length(length)
+}
+
+/// Fraction of the colour values of an image of the given dimensions used to hide `length` bytes
+#[pyfunction]
+fn embedding_rate(length: usize, width: u32, height: u32) -> f64 {
+    analysis::embedding_rate(length, width, height)
+}
+
+/// Estimate how detectable hiding `length` bytes in an image is, `low`, `medium` or `high`, or `None` if it does not fit
+#[pyfunction]
+fn detectability(
+    method: &str,
+    max_bit: u8,
+    distribution: &str,
+    length: usize,
+    width: u32,
+    height: u32,
+) -> PyResult<Option<String>> {
+    let method: StegMethod = parse(method)?;
+    let distribution: BitDistribution = parse(distribution)?;
+    Ok(
+        analysis::detectability(method, max_bit, &distribution, length, width, height)
+            .map(|detectability| detectability.to_string()),
+    )
+}
+
+/// Every combination of method, max bit and distribution that can be used to hide a message
+#[pyfunction]
+fn combinations() -> Vec<(String, u8, String)> {
+    analysis::combinations()
+        .into_iter()
+        .map(|(method, max_bit, distribution)| {
+            (method.to_string(), max_bit, distribution.to_string())
+        })
+        .collect()
+}
+
+/// Python bindings to hide and reveal messages in images held in numpy arrays
+#[pymodule]
+fn stegosaurust(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    let py = m.py();
+    m.add("StegError", py.get_type::<StegError>())?;
+    m.add("CompressionError", py.get_type::<CompressionError>())?;
+    m.add("CryptoError", py.get_type::<CryptoError>())?;
+    m.add_class::<PySteganography>()?;
+    m.add_class::<PyLsb>()?;
+    m.add_class::<PyRsb>()?;
+    m.add_function(wrap_pyfunction!(compress, m)?)?;
+    m.add_function(wrap_pyfunction!(decompress, m)?)?;
+    m.add_function(wrap_pyfunction!(encrypt, m)?)?;
+    m.add_function(wrap_pyfunction!(decrypt, m)?)?;
+    m.add_function(wrap_pyfunction!(capacity, m)?)?;
+    m.add_function(wrap_pyfunction!(min_square_width, m)?)?;
+    m.add_function(wrap_pyfunction!(encrypted_len, m)?)?;
+    m.add_function(wrap_pyfunction!(base64_len, m)?)?;
+    m.add_function(wrap_pyfunction!(linear_length, m)?)?;
+    m.add_function(wrap_pyfunction!(embedding_rate, m)?)?;
+    m.add_function(wrap_pyfunction!(detectability, m)?)?;
+    m.add_function(wrap_pyfunction!(combinations, m)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_errors_map_to_exceptions() {
+        Python::initialize();
+        Python::attach(|py| {
+            let err = PyErr::from(crate::StegError::EncodingNotFound);
+            assert!(err.is_instance_of::<StegError>(py));
+            assert_eq!(
+                err.value(py).getattr("args").unwrap().to_string(),
+                "('Encoded message not found in data', 'STEG_ENCODING_NOT_FOUND')"
+            );
+            let err = PyErr::from(crate::StegError::Crypto(crate::CryptoError::Salt));
+            assert!(err.is_instance_of::<CryptoError>(py));
+            assert!(err.is_instance_of::<StegError>(py));
+            let err = PyErr::from(crate::StegError::Compression(
+                crate::CompressionError::EmptyData,
+            ));
+            assert!(err.is_instance_of::<CompressionError>(py));
+        });
+    }
+
+    #[test]
+    fn test_compress_encrypt_round_trip() {
+        Python::initialize();
+        Python::attach(|py| {
+            let data = b"hello world, hello world";
+            let packed = encrypt(py, compress(py, data).unwrap().as_bytes(), b"key").unwrap();
+            let unpacked = decrypt(py, packed.as_bytes(), b"key").unwrap();
+            assert_eq!(
+                decompress(py, unpacked.as_bytes()).unwrap().as_bytes(),
+                data
+            );
+            let err = decrypt(py, packed.as_bytes(), b"wrong").unwrap_err();
+            assert!(err.is_instance_of::<CryptoError>(py));
+        });
+    }
+}
@@ -0,0 +1,240 @@
+use std::path::PathBuf;
+
+use serde::Serialize;
+
+use crate::analysis::Detectability;
+use crate::payload::{ChunkInfo, FileMetadata};
+use crate::{CompressionError, CryptoError, StegError};
+
+/// Result of running a subcommand
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum Report {
+    Encode(EncodeReport),
+    MaxLength(MaxLengthReport),
+    Decode(DecodeReport),
+    Disguise(DisguiseReport),
+    Capacity(CapacityReport),
+}
+
+#[derive(Serialize)]
+pub struct EncodeReport {
+    pub image: PathBuf,
+    pub output: Option<PathBuf>,
+    pub method: String,
+    /// Distribution to decode with, including the length needed to decode a linear distribution
+    pub distribution: String,
+    /// Length of the data hidden in the image, after any transformations
+    pub encoded_length: usize,
+    pub capacity: usize,
+}
+
+#[derive(Serialize)]
+pub struct MaxLengthReport {
+    pub image: PathBuf,
+    pub method: String,
+    pub capacity: usize,
+}
+
+#[derive(Serialize)]
+pub struct DecodeReport {
+    pub image: PathBuf,
+    pub method: String,
+    /// Length of the data hidden in the image, before any transformations are undone
+    pub encoded_length: usize,
+    pub encrypted: bool,
+    pub compressed: bool,
+    pub message_length: usize,
+    pub metadata: Option<FileMetadata>,
+    /// Where the message belongs in a file split across several images
+    pub chunk: Option<ChunkInfo>,
+    /// Files in a hidden archive
+    pub entries: Option<Vec<EntryReport>>,
+    /// File the message was written or restored to
+    pub output: Option<PathBuf>,
+    /// Files extracted from a hidden archive
+    pub extracted: Vec<PathBuf>,
+    /// Base64 encoded message, when not written to a file
+    pub data: Option<String>,
+    /// Message as text, when not written to a file and it is valid UTF-8
+    pub text: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct EntryReport {
+    pub path: String,
+    pub size: usize,
+    pub mode: Option<u32>,
+}
+
+#[derive(Serialize)]
+pub struct DisguiseReport {
+    pub files: Vec<FileReport>,
+}
+
+#[derive(Serialize)]
+pub struct FileReport {
+    pub path: PathBuf,
+    /// Image the file is hidden in
+    pub carrier: Option<PathBuf>,
+    /// Images each part of the file is hidden in, when it was split across several
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub parts: Vec<PathBuf>,
+    pub status: Status,
+    pub error: Option<ErrorReport>,
+}
+
+impl FileReport {
+    pub fn new(
+        path: PathBuf,
+        carrier: Option<PathBuf>,
+        status: Status,
+        error: Option<&anyhow::Error>,
+    ) -> Self {
+        FileReport {
+            path,
+            carrier,
+            parts: Vec::new(),
+            status,
+            error: error.map(ErrorReport::from),
+        }
+    }
+
+    /// Report a file split across several images, the first of which is its carrier
+    pub fn with_parts(mut self, parts: Vec<PathBuf>) -> Self {
+        if parts.len() > 1 {
+            self.carrier = parts.first().cloned();
+            self.parts = parts;
+        }
+        self
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Ok,
+    Skipped,
+    Failed,
+    /// The image a file was hidden in is listed in the manifest but no longer exists
+    Missing,
+    /// The file revealed from an image does not match the manifest
+    Tampered,
+    /// The file would be disguised or restored, but `--dry-run` was given
+    Planned,
+}
+
+#[derive(Serialize)]
+pub struct CapacityReport {
+    pub payload_size: usize,
+    pub compressed_size: Option<usize>,
+    /// Width of the smallest square cover that fits the payload.
+    ///
+    /// This is the theoretical minimum, `disguise` requests covers about twice as wide.
+    pub smallest_cover: u32,
+    /// Width of the smallest square cover that fits the compressed payload, the theoretical minimum
+    pub smallest_cover_compressed: Option<u32>,
+    pub covers: Vec<CoverReport>,
+}
+
+#[derive(Serialize)]
+pub struct CoverReport {
+    pub cover: PathBuf,
+    pub width: u32,
+    pub height: u32,
+    /// Capacity of the cover, the same for every method, max bit and distribution
+    pub capacity: usize,
+    pub fits: bool,
+    pub fits_compressed: Option<bool>,
+    pub plans: Vec<PlanReport>,
+}
+
+#[derive(Serialize)]
+pub struct PlanReport {
+    pub method: String,
+    pub max_bit: u8,
+    pub distribution: String,
+    pub detectability: Option<Detectability>,
+}
+
+#[derive(Serialize)]
+pub struct ErrorReport {
+    /// Stable code identifying the kind of error
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl From<&anyhow::Error> for ErrorReport {
+    fn from(err: &anyhow::Error) -> Self {
+        ErrorReport {
+            code: error_code(err),
+            message: format!("{:#}", err),
+        }
+    }
+}
+
+/// Derive a stable error code from the first known error in the chain of causes.
+pub fn error_code(err: &anyhow::Error) -> &'static str {
+    for cause in err.chain() {
+        if let Some(err) = cause.downcast_ref::<StegError>() {
+            return err.code();
+        }
+        if let Some(err) = cause.downcast_ref::<CompressionError>() {
+            return err.code();
+        }
+        if let Some(err) = cause.downcast_ref::<CryptoError>() {
+            return err.code();
+        }
+    }
+    for cause in err.chain() {
+        if cause.is::<std::io::Error>() {
+            return "IO";
+        }
+        if cause.is::<image::ImageError>() {
+            return "IMAGE";
+        }
+        if cause.is::<base64::DecodeError>() {
+            return "BASE64";
+        }
+    }
+    "UNKNOWN"
+}
+
+/// Print the result of running a subcommand as JSON
+pub fn print_json(result: &anyhow::Result<Report>) {
+    let json = match result {
+        Ok(report) => serde_json::json!({ "status": "ok", "result": report }),
+        Err(err) => serde_json::json!({ "status": "error", "error": ErrorReport::from(err) }),
+    };
+    println!("{}", json);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_error_code_from_cause() {
+        let err = Err::<(), _>(StegError::Crypto(CryptoError::Decryption(
+            "bad".to_string(),
+        )))
+        .context("failed to decode")
+        .unwrap_err();
+        assert_eq!(error_code(&err), "CRYPTO_DECRYPTION");
+    }
+
+    #[test]
+    fn test_error_code_base64() {
+        let err = anyhow::Error::new(StegError::from(base64::DecodeError::InvalidLength));
+        assert_eq!(error_code(&err), "STEG_BASE64");
+    }
+
+    #[test]
+    fn test_error_code_unknown() {
+        let err = anyhow::anyhow!("something went wrong");
+        assert_eq!(error_code(&err), "UNKNOWN");
+        let err = anyhow::Error::new(std::io::Error::from(std::io::ErrorKind::NotFound));
+        assert_eq!(error_code(&err), "IO");
+    }
+}
@@ -1,18 +1,38 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs::{DirEntry, File};
 use std::io::{stdin, stdout, Cursor, Read, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use anyhow::{bail, Context, Result};
 use atty::Stream;
+use globset::{Glob, GlobSet, GlobSetBuilder};
 use image::io::Reader as ImageReader;
-use log::{debug, error, warn};
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{debug, error, info, warn};
+use notify_debouncer_mini::notify::RecursiveMode;
+use notify_debouncer_mini::{new_debouncer, DebounceEventResult};
 use pretty_bytes::converter::convert;
-use tabled::Table;
+use rand::Rng;
+use tabled::{Table, Tabled};
+use walkdir::WalkDir;
 
+use crate::analysis;
+use crate::archive::Archive;
 use crate::cli;
-use crate::compress::{compress, decompress};
-use crate::crypto;
-use crate::steganography::encoder_from_opts;
+use crate::cli::{BitDistribution, CarrierNaming};
+use crate::compress::compress;
+use crate::manifest::{Manifest, ManifestEntry, MANIFEST_NAME};
+use crate::payload::{FileMetadata, Payload};
+use crate::report;
+use crate::report::{
+    CapacityReport, CoverReport, DecodeReport, DisguiseReport, EncodeReport, EntryReport,
+    FileReport, MaxLengthReport, PlanReport, Report, Status,
+};
+use crate::steganography::linear_length;
+use crate::stego::StegoBuilder;
 use crate::StegError;
 
 use crate::image_api::{self, ImageApi};
@@ -26,251 +46,1537 @@ fn load_rgb8_img(path: &PathBuf) -> Result<image::RgbImage> {
 
 /// Performs the steganography from the given command line options. Called from `main`.
 pub fn run(opt: cli::Opt) -> Result<()> {
-    match opt.cmd {
-        cli::Command::Disguise(opts) => disguise(opts),
+    let format = opt.format;
+    let result = run_command(opt.cmd, format);
+    match (format, result) {
+        (cli::Format::Text, result) => result.map(|_| ()),
+        (cli::Format::Json, result) => {
+            report::print_json(&result);
+            result.map(|_| ())
+        }
+    }
+}
+
+fn run_command(cmd: cli::Command, format: cli::Format) -> Result<Report> {
+    match cmd {
+        cli::Command::Disguise(opts) => disguise(opts, format).map(Report::Disguise),
         cli::Command::Encode(opts) => {
             let rgb8_img = load_rgb8_img(&opts.image)?;
-            encode(opts, rgb8_img)
+            if opts.opts.decode && !opts.check_max_length {
+                warn!("`encode --decode` is deprecated, use the `decode` subcommand instead");
+                return decode(opts.into(), rgb8_img, format).map(Report::Decode);
+            }
+            encode(opts, rgb8_img, format)
+        }
+        cli::Command::Capacity(opts) => capacity(opts, format).map(Report::Capacity),
+        cli::Command::Decode(opts) => {
+            let rgb8_img = load_rgb8_img(&opts.image)?;
+            decode(opts, rgb8_img, format).map(Report::Decode)
         }
     }
 }
 
 /// perform an encoding
-fn encode(opt: cli::Encode, mask: image::RgbImage) -> Result<()> {
-    // let rgb8_img = load_rgb8_img(&opt.image)?;
-
+fn encode(opt: cli::Encode, mask: image::RgbImage, format: cli::Format) -> Result<Report> {
     let steg_method = opt.opts.method.unwrap_or_default();
 
-    // create encoder
-    let mut encoder = encoder_from_opts(opt.opts.clone());
-
-    let max_msg_len = encoder.max_len(&mask);
     if opt.check_max_length {
+        let report = MaxLengthReport {
+            image: opt.image,
+            method: steg_method.to_string(),
+            capacity: StegoBuilder::from(&opt.opts).build()?.capacity(&mask),
+        };
+        if format == cli::Format::Json {
+            return Ok(Report::MaxLength(report));
+        }
         let table = Table::new(vec![
-            ("Image", opt.image.to_str().unwrap()),
+            ("Image", report.image.to_str().unwrap()),
             ("Encoding Method", &format!("{:?}", steg_method)),
-            ("Max Message Length", &convert(max_msg_len as f64)),
+            ("Max Message Length", &convert(report.capacity as f64)),
         ])
         .with(tabled::Style::blank())
         .with(tabled::Disable::Row(..1))
         .with(tabled::Modify::new(tabled::object::Segment::all()).with(tabled::Alignment::left()))
         .to_string();
         println!("{}", table);
-        return Ok(());
+        return Ok(Report::MaxLength(report));
+    }
+    if opt.output.is_none() && format == cli::Format::Json {
+        bail!("an output file is required to encode with --format json");
     }
 
-    if opt.opts.decode {
-        let mut result = encoder
-            .decode(&mask)
-            .context("failed to decode message from image")?;
-
-        // perform transformations if necessary, decode then decrypt
-        if opt.opts.base64 {
-            result = base64::decode(result)?;
+    // read message to encode to image from file/stdin
+    let message = match opt.input.as_slice() {
+        [path] if !path.is_dir() => {
+            let mut file =
+                File::open(path).context(format!("failed to read {}", path.to_str().unwrap()))?;
+            let mut buffer = Vec::new();
+            file.read_to_end(&mut buffer)?;
+            if opt.embed_metadata {
+                let metadata = FileMetadata::from_path(path, &buffer)
+                    .context(format!("failed to read metadata of {}", path.display()))?;
+                buffer = Payload::File {
+                    metadata,
+                    data: buffer,
+                }
+                .into_bytes();
+            }
+            buffer
         }
+        [] => {
+            let mut buffer = Vec::new();
+            if atty::is(Stream::Stdin) {
+                print!("Enter message to encode: ");
+                let _ = stdout().flush();
+                let mut str_buf = String::new();
+                stdin().read_line(&mut str_buf)?;
+                buffer = str_buf.into_bytes();
+            } else {
+                stdin().read_to_end(&mut buffer)?;
+            }
+            buffer
+        }
+        paths => {
+            let archive = Archive::from_paths(paths).context("failed to archive input files")?;
+            debug!("archived {} files", archive.entries.len());
+            Payload::Archive(archive).into_bytes()
+        }
+    };
+    let linear = matches!(opt.opts.distribution, Some(BitDistribution::Linear { .. }));
+    let to_stdout = opt.output.is_none();
+    let report = hide(opt, message, mask)?;
+    if let (Report::Encode(encoded), true, cli::Format::Text) = (&report, linear, format) {
+        // keep the note out of the image when it is written to stdout
+        let note = format!(
+            "Note: use length '{}' when decoding with linear distribution",
+            linear_length(encoded.encoded_length)
+        );
+        if to_stdout {
+            eprintln!("{}", note);
+        } else {
+            println!("{}", note);
+        }
+    }
+    Ok(report)
+}
+
+/// Hide a message in an image, writing it to the output
+fn hide(opt: cli::Encode, message: Vec<u8>, mask: image::RgbImage) -> Result<Report> {
+    let steg_method = opt.opts.method.unwrap_or_default();
+    let stego = StegoBuilder::from(&opt.opts).build()?;
+    let max_msg_len = stego.capacity(&mask);
 
-        if let Some(key) = opt.opts.key {
-            result = crypto::decrypt(&result, key.as_bytes()).map_err(StegError::Crypto)?;
+    // perform transformations if necessary, encrypt then encode
+    let message = stego.pack(&message)?;
+
+    // check for message too long!
+    if message.len() > max_msg_len {
+        bail!(
+            "Mesesage is too long, exceeds capacity that can fit in the image supplied. {} > {}
+Try again using the compression flag --compress/-c, if not please use a larger image or less data",
+            convert(message.len() as f64),
+            convert(max_msg_len as f64)
+        );
+    }
+
+    // encode
+    let result = stego
+        .embed(&mask, &message)
+        .context("failed to encode message")?;
+    match &opt.output {
+        Some(path) => {
+            // the temporary file has no extension to guess the format from
+            let format = image::ImageFormat::from_path(path)?;
+            write_atomic(path, |tmp| Ok(result.save_with_format(tmp, format)?))?;
+        }
+        None => {
+            let mut out = std::io::stdout();
+            out.write_all(result.as_raw())?;
+            out.flush()?;
         }
+    }
+
+    let distribution = match opt.opts.distribution.unwrap_or_default() {
+        BitDistribution::Linear { .. } => BitDistribution::Linear {
+            length: linear_length(message.len()),
+        },
+        dist => dist,
+    };
+    Ok(Report::Encode(EncodeReport {
+        image: opt.image,
+        output: opt.output,
+        method: steg_method.to_string(),
+        distribution: distribution.to_string(),
+        encoded_length: message.len(),
+        capacity: max_msg_len,
+    }))
+}
 
-        if opt.opts.compress {
-            result = decompress(&result).map_err(StegError::Compression)?;
+/// perform a decoding
+fn decode(opt: cli::Decode, mask: image::RgbImage, format: cli::Format) -> Result<DecodeReport> {
+    let steg_method = opt.opts.method.unwrap_or_default();
+
+    let revealed = reveal(&opt.opts, &mask)?;
+
+    let mut report = DecodeReport {
+        image: opt.image.clone(),
+        method: steg_method.to_string(),
+        encoded_length: revealed.encoded_len,
+        encrypted: revealed.encrypted,
+        compressed: revealed.compressed,
+        message_length: revealed.payload.size(),
+        metadata: revealed.payload.metadata().cloned(),
+        chunk: revealed.payload.chunk().cloned(),
+        entries: match &revealed.payload {
+            Payload::Archive(archive) => Some(
+                archive
+                    .entries
+                    .iter()
+                    .map(|entry| EntryReport {
+                        path: entry.path.clone(),
+                        size: entry.data.len(),
+                        mode: entry.mode,
+                    })
+                    .collect(),
+            ),
+            _ => None,
+        },
+        output: None,
+        extracted: Vec::new(),
+        data: None,
+        text: None,
+    };
+
+    if opt.metadata {
+        if format == cli::Format::Text {
+            print_metadata(&report);
         }
+        return Ok(report);
+    }
 
-        if let Some(path) = opt.output {
-            let mut f = File::create(&path)
-                .context(format!("failed to create file: {}", path.to_str().unwrap()))?;
-            f.write_all(&result)
-                .context("failed to write message to file")?;
-        } else {
-            let result = match String::from_utf8(result.clone()) {
-                Ok(s) => s,
-                Err(_) => unsafe { String::from_utf8_unchecked(result) },
-            };
-            print!("{}", result);
+    let (metadata, data) = match revealed.payload {
+        Payload::Archive(archive) => return unpack(opt, archive, format, report),
+        Payload::Raw(data) => (None, data),
+        Payload::File { metadata, data } => (Some(metadata), data),
+        Payload::Chunk {
+            metadata, chunk, ..
+        } => bail!(
+            "the image hides part {} of {} of {}, restore it with `disguise --decode`",
+            chunk.index + 1,
+            chunk.total,
+            metadata.name
+        ),
+    };
+    if opt.list || opt.extract.is_some() {
+        bail!("no archive hidden in the image to list or extract files from");
+    }
+
+    if opt.restore {
+        let metadata = metadata.context(
+            "no file metadata hidden in the image to restore from, use --output instead",
+        )?;
+        let name = metadata
+            .file_name()
+            .context(format!("invalid hidden file name: {:?}", metadata.name))?;
+        let path = opt.output.unwrap_or_default().join(name);
+        if path.exists() {
+            bail!("refusing to overwrite existing file: {}", path.display());
         }
+        write_payload(&path, &data, Some(&metadata))?;
+        info!("restored {}", path.display());
+        report.output = Some(path);
+        return Ok(report);
+    }
+
+    if let Some(path) = opt.output {
+        write_payload(&path, &data, metadata.as_ref())?;
+        report.output = Some(path);
+    } else if format == cli::Format::Json {
+        report.data = Some(base64::encode(&data));
+        report.text = String::from_utf8(data).ok();
     } else {
-        // read message to encode to image from file/stdin
-        let mut message = match &opt.input {
-            Some(path) => {
-                let mut file = File::open(path)
-                    .context(format!("failed to read {}", path.to_str().unwrap()))?;
-                let mut buffer = Vec::new();
-                file.read_to_end(&mut buffer)?;
-                buffer
-            }
-            None => {
-                let mut buffer = Vec::new();
-                if atty::is(Stream::Stdin) {
-                    print!("Enter message to encode: ");
-                    let _ = stdout().flush();
-                    let mut str_buf = String::new();
-                    stdin().read_line(&mut str_buf)?;
-                    buffer = str_buf.into_bytes();
-                } else {
-                    stdin().read_to_end(&mut buffer)?;
-                }
-                buffer
-            }
+        let result = match String::from_utf8(data.clone()) {
+            Ok(s) => s,
+            Err(_) => unsafe { String::from_utf8_unchecked(data) },
         };
+        print!("{}", result);
+    }
+    Ok(report)
+}
 
-        // perform transformations if necessary, encrypt then encode
+/// Print a table of metadata about the payload revealed from an image
+fn print_metadata(report: &DecodeReport) {
+    let yes_no = |b: bool| if b { "yes" } else { "no" }.to_string();
+    let mut rows = vec![
+        ("Image", report.image.display().to_string()),
+        ("Encoding Method", report.method.clone()),
+        ("Encoded Length", convert(report.encoded_length as f64)),
+        ("Encrypted", yes_no(report.encrypted)),
+        ("Compressed", yes_no(report.compressed)),
+        ("Message Length", convert(report.message_length as f64)),
+    ];
+    if let Some(entries) = &report.entries {
+        rows.push(("Archive Entries", entries.len().to_string()));
+    }
+    if let Some(metadata) = &report.metadata {
+        rows.push(("File Name", metadata.name.clone()));
+        if let Some(mode) = metadata.mode {
+            rows.push(("Permissions", format!("{:o}", mode)));
+        }
+        if let Some(modified) = metadata.modified {
+            let modified = UNIX_EPOCH + Duration::from_secs(modified);
+            rows.push((
+                "Modified",
+                humantime::format_rfc3339_seconds(modified).to_string(),
+            ));
+        }
+        if let Some(content_type) = &metadata.content_type {
+            rows.push(("Content Type", content_type.clone()));
+        }
+    }
+    if let Some(chunk) = &report.chunk {
+        rows.push(("Part", format!("{} of {}", chunk.index + 1, chunk.total)));
+    }
+    let table = Table::new(rows)
+        .with(tabled::Style::blank())
+        .with(tabled::Disable::Row(..1))
+        .with(tabled::Modify::new(tabled::object::Segment::all()).with(tabled::Alignment::left()))
+        .to_string();
+    println!("{}", table);
+}
 
-        if opt.opts.compress {
-            message = compress(&message).map_err(StegError::Compression)?;
+/// List or extract the files of an archive revealed from an image
+fn unpack(
+    opt: cli::Decode,
+    archive: Archive,
+    format: cli::Format,
+    mut report: DecodeReport,
+) -> Result<DecodeReport> {
+    if opt.list {
+        if format == cli::Format::Text {
+            let mut rows = vec![("Path".to_string(), "Size".to_string(), "Mode".to_string())];
+            rows.extend(archive.entries.iter().map(|entry| {
+                (
+                    entry.path.clone(),
+                    convert(entry.data.len() as f64),
+                    entry.mode.map(|m| format!("{:o}", m)).unwrap_or_default(),
+                )
+            }));
+            let table = Table::new(rows)
+                .with(tabled::Style::blank())
+                .with(tabled::Disable::Row(..1))
+                .with(
+                    tabled::Modify::new(tabled::object::Segment::all())
+                        .with(tabled::Alignment::left()),
+                )
+                .to_string();
+            println!("{}", table);
         }
+        return Ok(report);
+    }
 
-        if let Some(key) = &opt.opts.key {
-            message = crypto::encrypt(&message, key.as_bytes()).map_err(StegError::Crypto)?;
+    let dir = opt.extract.context(format!(
+        "an archive of {} files is hidden in the image, use --list or --extract",
+        archive.entries.len()
+    ))?;
+    for entry in archive.select(&opt.entries)? {
+        let path = entry
+            .extract(&dir)
+            .context(format!("failed to extract {}", entry.path))?;
+        info!("extracted {}", path.display());
+        report.extracted.push(path);
+    }
+    Ok(report)
+}
+
+/// The payload revealed from an image, along with how it was stored
+struct Revealed {
+    payload: Payload,
+    /// Length of the data as it was hidden in the image
+    encoded_len: usize,
+    encrypted: bool,
+    compressed: bool,
+}
+
+/// Reveal the payload hidden in an image, undoing any transformations applied when encoding
+fn reveal(opts: &cli::DecodeOpts, mask: &image::RgbImage) -> Result<Revealed> {
+    let mut stego = StegoBuilder::from(&cli::EncodeOpts::from(opts.clone()));
+    if let Some(key) = decode_key(opts)? {
+        stego = stego.encrypt(key);
+    }
+    let revealed = stego
+        .build()?
+        .reveal_details(mask)
+        .context("failed to decode message from image")?;
+
+    Ok(Revealed {
+        payload: Payload::from_bytes(revealed.data)?,
+        encoded_len: revealed.encoded_len,
+        encrypted: revealed.encrypted,
+        compressed: revealed.compressed,
+    })
+}
+
+/// Write revealed data to a file, restoring any file metadata hidden along with it
+fn write_payload(path: &Path, data: &[u8], metadata: Option<&FileMetadata>) -> Result<()> {
+    write_atomic(path, |tmp| {
+        let mut f =
+            File::create(tmp).context(format!("failed to create file: {}", path.display()))?;
+        f.write_all(data)
+            .context("failed to write message to file")?;
+        f.sync_all()?;
+        if let Some(metadata) = metadata {
+            metadata
+                .apply(tmp)
+                .context(format!("failed to restore metadata of {}", path.display()))?;
         }
+        Ok(())
+    })
+}
 
-        if opt.opts.base64 {
-            message = base64::encode(&message).as_bytes().to_vec();
+/// Write a file by writing a temporary file next to it and renaming it into place,
+/// so the file is never left partly written.
+fn write_atomic(path: &Path, write: impl FnOnce(&Path) -> Result<()>) -> Result<()> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let tmp = path.with_file_name(format!(".{}.{}.tmp", name, std::process::id()));
+    let result = write(&tmp).and_then(|_| {
+        std::fs::rename(&tmp, path).context(format!("failed to write {}", path.display()))
+    });
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+    result
+}
+
+/// Get the key to decrypt with, either given directly or read from an identity file
+fn decode_key(opts: &cli::DecodeOpts) -> Result<Option<String>> {
+    match (&opts.key, &opts.identity) {
+        (Some(key), _) => Ok(Some(key.clone())),
+        (None, Some(path)) => {
+            let key = std::fs::read_to_string(path)
+                .context(format!("failed to read key from {}", path.display()))?;
+            Ok(Some(key.trim_end_matches(&['\r', '\n'][..]).to_string()))
         }
+        (None, None) => Ok(None),
+    }
+}
 
-        // check for message too long!
-        if message.len() > max_msg_len {
-            bail!(
-                "Mesesage is too long, exceeds capacity that can fit in the image supplied. {} > {}
-Try again using the compression flag --compress/-c, if not please use a larger image or less data",
-                convert(message.len() as f64),
-                convert(max_msg_len as f64)
-            );
+/// Plan which settings and cover images can hide a payload
+fn capacity(opt: cli::Capacity, format: cli::Format) -> Result<CapacityReport> {
+    let (payload_len, compressed_len) = match &opt.input {
+        Some(path) => {
+            let data = std::fs::read(path).context(format!("failed to read {}", path.display()))?;
+            let compressed_len = compress(&data).ok().map(|c| c.len());
+            (data.len(), compressed_len)
+        }
+        None => (opt.size.unwrap_or_default(), None),
+    };
+    // account for the transformations applied before hiding the payload
+    let encoded_len = |len: usize| {
+        let len = if opt.encrypt {
+            analysis::encrypted_len(len)
+        } else {
+            len
+        };
+        if opt.base64 {
+            analysis::base64_len(len)
+        } else {
+            len
+        }
+    };
+    let payload_len = encoded_len(payload_len);
+    let compressed_len = compressed_len.map(encoded_len);
+
+    let covers = match &opt.cover {
+        Some(cover) if cover.is_dir() => {
+            let mut covers = std::fs::read_dir(cover)
+                .context(format!("reading {:?}", cover))?
+                .filter_map(|r| r.ok())
+                .filter(is_not_hidden)
+                .map(|dirent| dirent.path())
+                .filter(|path| path.is_file())
+                .collect::<Vec<_>>();
+            covers.sort();
+            covers
         }
+        Some(cover) => vec![cover.clone()],
+        None => Vec::new(),
+    };
 
-        // encode
-        let result = encoder
-            .encode(&mask, &message)
-            .context("failed to encode message")?;
-        match opt.output {
-            Some(path) => {
-                result.save(path)?;
+    let mut report = CapacityReport {
+        payload_size: payload_len,
+        compressed_size: compressed_len,
+        smallest_cover: analysis::min_square_width(payload_len),
+        smallest_cover_compressed: compressed_len.map(analysis::min_square_width),
+        covers: Vec::new(),
+    };
+    for path in covers {
+        let (width, height) = match image::image_dimensions(&path) {
+            Ok(dimensions) => dimensions,
+            Err(err) => {
+                debug!("skipping cover {}: {:?}", path.display(), err);
+                continue;
             }
-            None => {
-                let mut out = std::io::stdout();
-                out.write_all(result.as_raw())?;
-                out.flush()?;
+        };
+        let capacity = analysis::capacity(width, height);
+        // estimate detectability of the smallest form of the payload
+        let len = compressed_len.map_or(payload_len, |c| c.min(payload_len));
+        let plans = analysis::combinations()
+            .into_iter()
+            .map(|(method, max_bit, dist)| PlanReport {
+                method: method.to_string(),
+                max_bit,
+                distribution: dist.to_string(),
+                detectability: analysis::detectability(method, max_bit, &dist, len, width, height),
+            })
+            .collect();
+        report.covers.push(CoverReport {
+            cover: path,
+            width,
+            height,
+            capacity,
+            fits: payload_len <= capacity,
+            fits_compressed: compressed_len.map(|c| c <= capacity),
+            plans,
+        });
+    }
+
+    if format == cli::Format::Text {
+        print_capacity(&report, opt.cover.is_some());
+    }
+    Ok(report)
+}
+
+/// Print tables of the capacity plan
+fn print_capacity(report: &CapacityReport, with_covers: bool) {
+    let yes_no = |b: bool| if b { "yes" } else { "no" }.to_string();
+    if with_covers {
+        // capacity is the same for every combination, so only detectability is shown for each
+        let rows = report.covers.iter().map(|cover| CapacityRow {
+            cover: cover.cover.display().to_string(),
+            size: format!("{}x{}", cover.width, cover.height),
+            capacity: convert(cover.capacity as f64),
+            fits: yes_no(cover.fits),
+            fits_compressed: cover.fits_compressed.map_or("-".to_string(), yes_no),
+        });
+        print_table(Table::new(rows));
+
+        let rows = report.covers.iter().flat_map(|cover| {
+            cover.plans.iter().map(|plan| DetectabilityRow {
+                cover: cover.cover.display().to_string(),
+                method: plan.method.clone(),
+                max_bit: plan.max_bit,
+                distribution: plan.distribution.clone(),
+                detectability: plan
+                    .detectability
+                    .map_or("-".to_string(), |d| d.to_string()),
+            })
+        });
+        print_table(Table::new(rows));
+    }
+
+    let mut rows = vec![
+        ("Payload Size", convert(report.payload_size as f64)),
+        (
+            "Theoretical Minimum Square Cover",
+            format!("{}x{}", report.smallest_cover, report.smallest_cover),
+        ),
+    ];
+    if let (Some(compressed_len), Some(min_width)) =
+        (report.compressed_size, report.smallest_cover_compressed)
+    {
+        rows.push(("Compressed Size", convert(compressed_len as f64)));
+        rows.push((
+            "Theoretical Minimum Square Cover Compressed",
+            format!("{}x{}", min_width, min_width),
+        ));
+    }
+    print_table(Table::new(rows).with(tabled::Disable::Row(..1)));
+}
+
+/// Print a table without borders, left aligned
+fn print_table(table: Table) {
+    let table = table
+        .with(tabled::Style::blank())
+        .with(tabled::Modify::new(tabled::object::Segment::all()).with(tabled::Alignment::left()))
+        .to_string();
+    println!("{}", table);
+}
+
+/// A row of the capacity planner's table of covers
+#[derive(Tabled)]
+struct CapacityRow {
+    #[tabled(rename = "Cover")]
+    cover: String,
+    #[tabled(rename = "Size")]
+    size: String,
+    #[tabled(rename = "Capacity")]
+    capacity: String,
+    #[tabled(rename = "Fits")]
+    fits: String,
+    #[tabled(rename = "Fits Compressed")]
+    fits_compressed: String,
+}
+
+/// A row of the capacity planner's table of how detectable each combination is
+#[derive(Tabled)]
+struct DetectabilityRow {
+    #[tabled(rename = "Cover")]
+    cover: String,
+    #[tabled(rename = "Method")]
+    method: String,
+    #[tabled(rename = "Max Bit")]
+    max_bit: u8,
+    #[tabled(rename = "Distribution")]
+    distribution: String,
+    #[tabled(rename = "Detectability")]
+    detectability: String,
+}
+
+/// Disguise all files in directory by encoding them with assets embedded in the program
+fn disguise(opt: cli::Disguise, format: cli::Format) -> Result<DisguiseReport> {
+    let files = if opt.watch {
+        watch(&opt, format)?
+    } else if opt.opts.decode {
+        restore_disguised(&opt, format)?
+    } else {
+        hide_in_covers(&opt, format)?
+    };
+    if format == cli::Format::Text {
+        if opt.dry_run {
+            for file in files.iter().filter(|f| f.status == Status::Planned) {
+                let carriers = match &file.carrier {
+                    _ if !file.parts.is_empty() => file.parts.iter().collect(),
+                    Some(carrier) => vec![carrier],
+                    None => vec![&file.path],
+                };
+                for carrier in carriers {
+                    let (from, to) = match opt.opts.decode {
+                        true => (carrier, &file.path),
+                        false => (&file.path, carrier),
+                    };
+                    println!("{} ==> {}", from.display(), to.display());
+                }
             }
+        } else {
+            print_summary(&files, opt.opts.decode);
         }
     }
-    Ok(())
+    Ok(DisguiseReport { files })
 }
 
-/// Disguise all files in directory by encoding them with assets embedded in the program
-fn disguise(opt: cli::Disguise) -> Result<()> {
-    if opt.opts.decode {
-        for dirent in std::fs::read_dir(&opt.dir)
-            .context(format!("reading {:?}", opt.dir))?
-            .filter_map(|r| r.ok())
-            .filter(is_not_hidden)
-        {
-            if dirent.path().is_file() {
-                let path = dirent.path();
-                let fname = path.file_stem().unwrap();
-                let fname = fname.to_str().unwrap();
-                let original_fname = match base64::decode(fname.as_bytes()) {
-                    Ok(res) => res,
-                    Err(err) => {
-                        warn!(
-                            "error decoding original filename from {:?}: {:?}",
-                            path, err
-                        );
-                        continue;
-                    }
+/// Print how many files were disguised or restored, skipped or failed
+fn print_summary(files: &[FileReport], restoring: bool) {
+    let count = |status: Status| files.iter().filter(|f| f.status == status).count();
+    let mut summary = format!(
+        "{} {} files, {} skipped, {} failed",
+        if restoring { "restored" } else { "disguised" },
+        count(Status::Ok),
+        count(Status::Skipped),
+        count(Status::Failed),
+    );
+    for (status, name) in [(Status::Missing, "missing"), (Status::Tampered, "tampered")] {
+        if count(status) > 0 {
+            summary.push_str(&format!(", {} {}", count(status), name));
+        }
+    }
+    println!("{}", summary);
+}
+
+/// Run `job` on each file with `jobs` threads, showing progress, returning the results in the order of the files
+fn run_jobs<T: Send>(
+    jobs: usize,
+    paths: &[PathBuf],
+    format: cli::Format,
+    job: impl Fn(&Path) -> T + Sync,
+) -> Vec<T> {
+    let sizes = paths
+        .iter()
+        .map(|p| p.metadata().map(|m| m.len()).unwrap_or(0))
+        .collect::<Vec<_>>();
+    let progress = match format {
+        cli::Format::Text => ProgressBar::new(sizes.iter().sum()),
+        cli::Format::Json => ProgressBar::hidden(),
+    };
+    progress.set_style(
+        ProgressStyle::with_template(
+            "[{elapsed_precise}] {bar:40} {bytes}/{total_bytes} ({bytes_per_sec}, ETA {eta}) {msg}",
+        )
+        // SAFETY: the template is valid
+        .unwrap(),
+    );
+    progress.set_message(format!("0/{} files", paths.len()));
+
+    let next = AtomicUsize::new(0);
+    let done = AtomicUsize::new(0);
+    let reports = Mutex::new(Vec::with_capacity(paths.len()));
+    std::thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, paths.len().max(1)) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(path) = paths.get(i) else {
+                    break;
                 };
-                let original_fname = match std::str::from_utf8(&original_fname) {
-                    Ok(res) => res,
-                    Err(err) => {
-                        warn!(
-                            "error deriving original filename from {:?}: {:?}",
-                            path, err
+                let report = job(path);
+                reports.lock().unwrap().push((i, report));
+                progress.inc(sizes[i]);
+                let done = done.fetch_add(1, Ordering::Relaxed) + 1;
+                progress.set_message(format!("{}/{} files", done, paths.len()));
+            });
+        }
+    });
+    progress.finish_and_clear();
+
+    let mut reports = reports.into_inner().unwrap();
+    reports.sort_by_key(|(i, _)| *i);
+    reports.into_iter().map(|(_, report)| report).collect()
+}
+
+/// Load the manifest of a disguised directory if there is one, which needs the key it was encrypted with
+fn load_manifest(opt: &cli::Disguise, dir: &Path) -> Result<Option<Manifest>> {
+    let path = Manifest::path(dir);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let key = opt
+        .opts
+        .key
+        .as_ref()
+        .context("a key is needed to read the manifest of the disguised directory")?;
+    let bytes = std::fs::read(&path).context(format!("reading {}", path.display()))?;
+    let manifest = Manifest::decrypt(&bytes, key.as_bytes()).context("reading manifest")?;
+    Ok(Some(manifest))
+}
+
+/// Write the manifest of a disguised directory, removing it once it is empty
+fn save_manifest(opt: &cli::Disguise, dir: &Path, manifest: &Manifest) -> Result<()> {
+    let path = Manifest::path(dir);
+    if manifest.entries.is_empty() {
+        if path.exists() {
+            std::fs::remove_file(&path).context(format!("removing {}", path.display()))?;
+        }
+        return Ok(());
+    }
+    let key = opt
+        .opts
+        .key
+        .as_ref()
+        .context("a key is needed to write a manifest")?;
+    write_atomic(&path, |tmp| {
+        std::fs::write(tmp, manifest.encrypt(key.as_bytes())?)?;
+        Ok(())
+    })
+    .context(format!("writing {}", path.display()))
+}
+
+/// Restore the files hidden in each image in a disguised directory.
+///
+/// Files recorded in the directory's manifest are verified before the image they are hidden in is removed.
+/// Files split across several images are restored once every part has been revealed.
+fn restore_disguised(opt: &cli::Disguise, format: cli::Format) -> Result<Vec<FileReport>> {
+    let out_dir = opt.out_dir.as_ref().unwrap_or(&opt.dir);
+    let manifest = load_manifest(opt, &opt.dir)?;
+    // entries are removed once restored, leaving any that could not be
+    let remaining = Mutex::new(manifest.clone().unwrap_or_default());
+    let split_files = Mutex::new(HashMap::new());
+
+    let paths = disguise_files(opt, false)?;
+    let mut files = run_jobs(opt.jobs, &paths, format, |path| {
+        let carrier = relative_name(&opt.dir, path);
+        let entry = manifest
+            .as_ref()
+            .and_then(|m| m.entries.iter().find(|e| e.carrier == carrier));
+        restore_file(opt, out_dir, path, entry, &remaining, &split_files).unwrap_or_else(|err| {
+            error!("error restoring {}: {:?}", path.display(), err);
+            Some(FileReport::new(
+                path.to_path_buf(),
+                None,
+                Status::Failed,
+                Some(&err),
+            ))
+        })
+    })
+    .into_iter()
+    .flatten()
+    .collect::<Vec<_>>();
+
+    for split in split_files.into_inner().unwrap().into_values() {
+        let parts = split
+            .parts
+            .into_values()
+            .map(|(carrier, _)| carrier)
+            .collect();
+        let err = anyhow::anyhow!(
+            "only found {} of the {} parts of {}",
+            split.found,
+            split.total,
+            split.path.display()
+        );
+        error!("{}", err);
+        files.push(FileReport::new(split.path, None, Status::Failed, Some(&err)).with_parts(parts));
+    }
+
+    let seen = paths
+        .iter()
+        .map(|path| relative_name(&opt.dir, path))
+        .collect::<Vec<_>>();
+    for entry in remaining
+        .into_inner()
+        .unwrap()
+        .entries
+        .iter()
+        .filter(|e| !seen.contains(&e.carrier))
+    {
+        let carrier = opt.dir.join(&entry.carrier);
+        let err = anyhow::Error::new(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("{} is missing, it hid {}", carrier.display(), entry.path),
+        ));
+        error!("{}", err);
+        files.push(FileReport::new(
+            out_dir.join(&entry.path),
+            Some(carrier),
+            Status::Missing,
+            Some(&err),
+        ));
+    }
+    Ok(files)
+}
+
+/// The parts of a file split across several images revealed so far
+struct SplitFile {
+    /// Where the file is restored to
+    path: PathBuf,
+    total: u32,
+    /// Number of parts revealed, which is more than in `parts` if any were hidden twice
+    found: usize,
+    /// The image each part was revealed from and its data, by index
+    parts: BTreeMap<u32, (PathBuf, Vec<u8>)>,
+}
+
+/// Restore the file hidden in a single image, verifying it against its manifest entry if it has one.
+///
+/// Returns no report for a part of a split file until the last of its parts is revealed.
+fn restore_file(
+    opt: &cli::Disguise,
+    out_dir: &Path,
+    path: &Path,
+    entry: Option<&ManifestEntry>,
+    remaining: &Mutex<Manifest>,
+    split_files: &Mutex<HashMap<u64, SplitFile>>,
+) -> Result<Option<FileReport>> {
+    let path = path.to_path_buf();
+    // failures to reveal a file listed in the manifest mean its image was changed
+    let failed = if entry.is_some() {
+        Status::Tampered
+    } else {
+        Status::Failed
+    };
+
+    let mask = match load_rgb8_img(&path) {
+        Ok(mask) => mask,
+        Err(err) if entry.is_some() => {
+            error!("error reading {}: {:?}", path.display(), err);
+            return Ok(Some(FileReport::new(path, None, failed, Some(&err))));
+        }
+        Err(err) => {
+            warn!("skipping {}: {:?}", path.display(), err);
+            return Ok(Some(FileReport::new(
+                path,
+                None,
+                Status::Skipped,
+                Some(&err),
+            )));
+        }
+    };
+
+    debug!("decoding {}", path.display());
+
+    let revealed = match reveal(&opt.opts.clone().into(), &mask) {
+        Ok(revealed) => revealed,
+        Err(err) => {
+            error!("error decoding {}: {:?}", path.display(), err);
+            return Ok(Some(FileReport::new(path, None, failed, Some(&err))));
+        }
+    };
+
+    let (metadata, chunk, data) = match revealed.payload {
+        Payload::Archive(_) => {
+            let err = anyhow::anyhow!(
+                "{:?} contains an archive, extract it with `decode --extract`",
+                path
+            );
+            warn!("{}", err);
+            return Ok(Some(FileReport::new(
+                path,
+                None,
+                Status::Skipped,
+                Some(&err),
+            )));
+        }
+        Payload::Raw(data) => (None, None, data),
+        Payload::File { metadata, data } => (Some(metadata), None, data),
+        Payload::Chunk {
+            metadata,
+            chunk,
+            data,
+        } => (Some(metadata), Some(chunk), data),
+    };
+
+    let new_path = match entry {
+        // the manifest is trusted over anything hidden in the image
+        Some(entry) => {
+            let verified =
+                entry
+                    .verify(&data)
+                    .map_err(anyhow::Error::new)
+                    .and_then(|_| match entry.part == chunk {
+                        true => Ok(()),
+                        false => Err(anyhow::Error::new(StegError::Verification(format!(
+                            "{} does not hide the part of {} it should",
+                            entry.carrier, entry.path
+                        )))),
+                    });
+            if let Err(err) = verified {
+                error!("{} has been tampered with: {:?}", path.display(), err);
+                return Ok(Some(FileReport::new(
+                    path,
+                    None,
+                    Status::Tampered,
+                    Some(&err),
+                )));
+            }
+            out_dir.join(
+                safe_relative_path(&entry.path)
+                    .context(format!("unsafe path in manifest: {}", entry.path))?,
+            )
+        }
+        // prefer the name hidden with the file, falling back to the name of the image
+        None => {
+            let original_fname = match &metadata {
+                Some(metadata) => match metadata.file_name() {
+                    Some(name) => name.to_owned(),
+                    None => {
+                        let err = anyhow::anyhow!(
+                            "invalid original filename {:?} hidden in {:?}",
+                            metadata.name,
+                            path
                         );
-                        continue;
+                        warn!("{}", err);
+                        return Ok(Some(FileReport::new(
+                            path,
+                            None,
+                            Status::Skipped,
+                            Some(&err),
+                        )));
                     }
-                };
-                let mut new_path = path.clone();
-                new_path.set_file_name(original_fname);
-
-                let mask = load_rgb8_img(&path)?;
-
-                debug!("decoding {} ==> {}", path.display(), new_path.display());
-
-                match encode(
-                    cli::Encode {
-                        check_max_length: false,
-                        opts: opt.opts.clone(),
-                        input: None,
-                        output: Some(new_path), // where to restore
-                        image: path.clone(),    // image to decode
-                    },
-                    mask,
-                ) {
-                    Ok(_) => std::fs::remove_file(path)?,
+                },
+                None => match original_fname_from_stem(&path) {
+                    Ok(name) => name.into(),
                     Err(err) => {
-                        error!("error decoding {}: {:?}", path.display(), err);
-                        continue;
+                        warn!("{:?}", err);
+                        return Ok(Some(FileReport::new(
+                            path,
+                            None,
+                            Status::Skipped,
+                            Some(&err),
+                        )));
                     }
-                }
+                },
+            };
+            let mut new_path = out_path(&opt.dir, out_dir, &path);
+            new_path.set_file_name(original_fname);
+            new_path
+        }
+    };
+
+    // collect the parts of a split file until the last is revealed
+    let (carriers, data) = match chunk {
+        None => (vec![path], data),
+        Some(chunk) => {
+            let mut split_files = split_files.lock().unwrap();
+            let split = split_files.entry(chunk.group).or_insert_with(|| SplitFile {
+                path: new_path.clone(),
+                total: chunk.total,
+                found: 0,
+                parts: BTreeMap::new(),
+            });
+            if split.total != chunk.total {
+                bail!(
+                    "{} is part of a file split into {} parts, not {}",
+                    path.display(),
+                    chunk.total,
+                    split.total
+                );
             }
+            split.found += 1;
+            split.parts.insert(chunk.index, (path, data));
+            if split.parts.len() < split.total as usize {
+                return Ok(None);
+            }
+            // SAFETY: the entry was just inserted
+            let split = split_files.remove(&chunk.group).unwrap();
+            let (carriers, parts): (Vec<_>, Vec<_>) = split.parts.into_values().unzip();
+            (carriers, parts.concat())
         }
-    } else {
-        let image_client = image_api::PicsumClient::new();
-        for dirent in std::fs::read_dir(&opt.dir)
-            .context(format!("reading {:?}", opt.dir))?
-            .filter_map(|r| r.ok())
-            .filter(is_not_hidden)
-        {
-            if dirent.path().is_file() {
-                let path = dirent.path();
-
-                let mut new_fname: PathBuf = dirent.path().clone();
-                new_fname.set_file_name(base64::encode(
-                    new_fname.file_name().unwrap().to_str().unwrap(),
-                ));
-                new_fname.set_extension("png");
-
-                debug!("encoding {} ==> {}", path.display(), new_fname.display());
-
-                let bytes_to_mask = path.metadata()?.len();
-                let width_of_img_to_request =
-                    image_api::get_square_image_width_from_bytes(bytes_to_mask as usize);
-
-                let mask = match image_client.get_square_image(width_of_img_to_request) {
-                    Ok(data) => ImageReader::new(Cursor::new(data))
-                        .with_guessed_format()?
-                        .decode()?
-                        .into_rgb8(),
-                    Err(err) => {
-                        error!(
-                            "fetching image from api width={} | {:?}",
-                            width_of_img_to_request, err
-                        );
-                        continue;
-                    }
-                };
+    };
+    let report = |status, err: Option<&anyhow::Error>| {
+        FileReport::new(new_path.clone(), carriers.first().cloned(), status, err)
+            .with_parts(carriers.clone())
+    };
 
-                match encode(
-                    cli::Encode {
-                        check_max_length: false,
-                        opts: opt.opts.clone(),
-                        input: Some(dirent.path()), // what to hide
-                        output: Some(new_fname),    // where to hide
-                        image: PathBuf::new(),      // not used as calling `encode` directly
-                    },
-                    mask, // image to hide in
-                ) {
-                    Ok(_) => std::fs::remove_file(dirent.path())?,
-                    Err(err) => {
-                        error!("error encoding {}: {:?}", path.display(), err);
-                        continue;
+    if opt.dry_run {
+        return Ok(Some(report(Status::Planned, None)));
+    }
+
+    debug!(
+        "restoring {} ==> {}",
+        carriers[0].display(),
+        new_path.display()
+    );
+
+    let restored = new_path
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .context(format!(
+            "failed to create directory for {}",
+            new_path.display()
+        ))
+        .and_then(|_| write_payload(&new_path, &data, metadata.as_ref()));
+    if let Err(err) = restored {
+        error!("error restoring {}: {:?}", new_path.display(), err);
+        return Ok(Some(report(Status::Failed, Some(&err))));
+    }
+    if !opt.keep_originals {
+        for carrier in &carriers {
+            std::fs::remove_file(carrier)?;
+        }
+        if entry.is_some() {
+            let carriers = carriers
+                .iter()
+                .map(|c| relative_name(&opt.dir, c))
+                .collect::<Vec<_>>();
+            let mut remaining = remaining.lock().unwrap();
+            remaining.entries.retain(|e| !carriers.contains(&e.carrier));
+            save_manifest(opt, &opt.dir, &remaining)?;
+        }
+    }
+    Ok(Some(report(Status::Ok, None)))
+}
+
+/// A path from a manifest as a path relative to the disguised directory, if it cannot escape it
+fn safe_relative_path(path: &str) -> Option<PathBuf> {
+    let path = Path::new(path);
+    if path.as_os_str().is_empty()
+        || !path
+            .components()
+            .all(|c| matches!(c, std::path::Component::Normal(_)))
+    {
+        return None;
+    }
+    Some(path.to_path_buf())
+}
+
+/// Where a file in `dir` is written to in `out_dir`, keeping its path relative to `dir`
+fn out_path(dir: &Path, out_dir: &Path, path: &Path) -> PathBuf {
+    out_dir.join(path.strip_prefix(dir).unwrap_or(path))
+}
+
+/// Hide each file in a directory in a cover image, removing the original once the image is confirmed to reveal it
+fn hide_in_covers(opt: &cli::Disguise, format: cli::Format) -> Result<Vec<FileReport>> {
+    let hider = Hider::new(opt, format)?;
+    let paths = disguise_files(opt, true)?;
+    Ok(hider.hide(&paths))
+}
+
+/// Disguise files as soon as they are written to the directory, until interrupted.
+///
+/// Files are disguised once they have not changed for the debounce time, so partly written files are left alone.
+fn watch(opt: &cli::Disguise, format: cli::Format) -> Result<Vec<FileReport>> {
+    let hider = Hider::new(opt, format)?;
+    let (tx, rx) = std::sync::mpsc::channel();
+    let mut debouncer = new_debouncer(opt.debounce.unwrap_or(Duration::from_secs(2)), tx)
+        .context("failed to create watcher")?;
+    let mode = match opt.recursive {
+        true => RecursiveMode::Recursive,
+        false => RecursiveMode::NonRecursive,
+    };
+    debouncer
+        .watcher()
+        .watch(&opt.dir, mode)
+        .context(format!("failed to watch {}", opt.dir.display()))?;
+    info!("watching {} for files to disguise", opt.dir.display());
+
+    disguise_changes(opt, &hider, rx, |disguised| match format {
+        cli::Format::Text => print_summary(&disguised, false),
+        cli::Format::Json => {
+            report::print_json(&Ok(Report::Disguise(DisguiseReport { files: disguised })))
+        }
+    });
+    // each batch of files is reported as it is disguised
+    Ok(Vec::new())
+}
+
+/// Disguise the files changed in each batch of debounced events until the watcher stops, passing the reports of
+/// each batch disguised to `report`.
+///
+/// Errors are logged rather than returned, so one bad batch does not stop the watch.
+fn disguise_changes(
+    opt: &cli::Disguise,
+    hider: &Hider,
+    events: impl IntoIterator<Item = DebounceEventResult>,
+    mut report: impl FnMut(Vec<FileReport>),
+) {
+    // images written while watching, which are not disguised again
+    let mut written = HashSet::new();
+    for events in events {
+        let events = match events {
+            Ok(events) => events,
+            Err(err) => {
+                error!("error watching {}: {:?}", opt.dir.display(), err);
+                continue;
+            }
+        };
+        let changed = events
+            .into_iter()
+            .filter_map(|event| event.path.canonicalize().ok())
+            .filter(|path| !written.contains(path))
+            .collect::<HashSet<_>>();
+        if changed.is_empty() {
+            continue;
+        }
+        // only disguise the files that would be disguised by running without watching
+        let paths = match disguise_files(opt, true) {
+            Ok(paths) => paths,
+            Err(err) => {
+                error!("error finding files in {}: {:?}", opt.dir.display(), err);
+                continue;
+            }
+        };
+        let paths = paths
+            .into_iter()
+            .filter(|path| path.canonicalize().is_ok_and(|p| changed.contains(&p)))
+            .collect::<Vec<_>>();
+        if paths.is_empty() {
+            continue;
+        }
+
+        let disguised = hider.hide(&paths);
+        for file in &disguised {
+            let carriers = match file.parts.is_empty() {
+                true => file.carrier.iter().collect::<Vec<_>>(),
+                false => file.parts.iter().collect(),
+            };
+            written.extend(carriers.into_iter().filter_map(|c| c.canonicalize().ok()));
+        }
+        report(disguised);
+    }
+}
+
+/// Hides files in cover images, sharing the source of covers, the manifest and the names of the images written
+/// between jobs.
+struct Hider<'a> {
+    opt: &'a cli::Disguise,
+    format: cli::Format,
+    out_dir: &'a Path,
+    image_client: Box<dyn ImageApi>,
+    manifest: Mutex<Manifest>,
+    /// Names of the images being written, so no two files are disguised as the same image
+    taken: Mutex<HashSet<PathBuf>>,
+    /// Most bytes of a file to hide in one image, before splitting it across several
+    chunk_size: usize,
+    /// Checks each image written reveals the data hidden in it, `verify_carrier` unless testing a failure
+    verify: fn(&Path, &cli::EncodeOpts, usize, &[u8]) -> Result<()>,
+}
+
+impl<'a> Hider<'a> {
+    fn new(opt: &'a cli::Disguise, format: cli::Format) -> Result<Self> {
+        let out_dir = opt.out_dir.as_ref().unwrap_or(&opt.dir);
+        let image_client: Box<dyn ImageApi> = match &opt.covers {
+            Some(dir) => Box::new(
+                image_api::LocalImages::new(
+                    dir,
+                    opt.select.unwrap_or_default(),
+                    opt.fit.unwrap_or_default(),
+                )
+                .context(format!("reading covers from {:?}", dir))?,
+            ),
+            None => match opt.generate {
+                Some(kind) => {
+                    let seed = opt.generate_seed.clone().unwrap_or_else(|| {
+                        let seed: u64 = rand::random();
+                        seed.to_string()
+                    });
+                    Box::new(image_api::GeneratedImages::new(kind, &seed))
+                }
+                #[cfg(feature = "net")]
+                None => Box::new(
+                    image_api::PicsumClient::with_config(opt.http.clone().into())
+                        .context("creating image api client")?,
+                ),
+                #[cfg(not(feature = "net"))]
+                None => bail!(
+                    "fetching cover images needs stegosaurust built with the `net` feature, use --covers or --generate instead"
+                ),
+            },
+        };
+        // add to the existing manifest when disguising more files in a directory
+        let manifest = Mutex::new(match opt.manifest {
+            true => load_manifest(opt, out_dir)?.unwrap_or_default(),
+            false => Manifest::new(),
+        });
+
+        Ok(Hider {
+            opt,
+            format,
+            out_dir,
+            image_client,
+            manifest,
+            taken: Mutex::new(HashSet::new()),
+            chunk_size: image_api::max_bytes_for_square_image(),
+            verify: verify_carrier,
+        })
+    }
+
+    /// Hide each file in a cover image, running the configured number of jobs at once
+    fn hide(&self, paths: &[PathBuf]) -> Vec<FileReport> {
+        run_jobs(self.opt.jobs, paths, self.format, |path| {
+            self.hide_file(path).unwrap_or_else(|err| {
+                error!("error encoding {}: {:?}", path.display(), err);
+                FileReport::new(path.to_path_buf(), None, Status::Failed, Some(&err))
+            })
+        })
+    }
+
+    /// Hide a single file in a cover image, splitting it across several if it is too large for one
+    fn hide_file(&self, path: &Path) -> Result<FileReport> {
+        let (opt, out_dir) = (self.opt, self.out_dir);
+        let path = path.to_path_buf();
+        let new_path = out_path(&opt.dir, out_dir, &path);
+
+        let size = std::fs::metadata(&path)
+            .context(format!("failed to read {}", path.display()))?
+            .len() as usize;
+        let chunk_size = self.chunk_size;
+        let parts = size.div_ceil(chunk_size).max(1);
+        let carriers = (1..=parts)
+            .map(|part| {
+                carrier_path(
+                    opt.naming.unwrap_or_default(),
+                    &new_path,
+                    part,
+                    parts,
+                    &self.taken,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        if opt.dry_run {
+            return Ok(
+                FileReport::new(path, carriers.first().cloned(), Status::Planned, None)
+                    .with_parts(carriers),
+            );
+        }
+
+        debug!("encoding {} ==> {}", path.display(), carriers[0].display());
+
+        let data = std::fs::read(&path).context(format!("failed to read {}", path.display()))?;
+        let metadata = FileMetadata::from_path(&path, &data)
+            .context(format!("failed to read metadata of {}", path.display()))?;
+        let payloads = match carriers.len() {
+            1 => vec![Payload::File {
+                metadata,
+                data: data.clone(),
+            }],
+            _ => Payload::chunks(&metadata, &data, chunk_size, rand::random()),
+        };
+        if payloads.len() != carriers.len() {
+            bail!("{} changed size while being disguised", path.display());
+        }
+
+        let parts = match carriers.len() {
+            1 => vec![data.as_slice()],
+            _ => data.chunks(chunk_size).collect(),
+        };
+
+        let mut entries = Vec::new();
+        for ((carrier, payload), part) in carriers.iter().zip(payloads).zip(parts) {
+            let chunk = payload.chunk().cloned();
+            let key = match &chunk {
+                Some(chunk) => format!("{}#{}", relative_name(&opt.dir, &path), chunk.index),
+                None => relative_name(&opt.dir, &path),
+            };
+            let hidden = hide_payload(
+                opt,
+                self.image_client.as_ref(),
+                &key,
+                carrier,
+                payload,
+                part,
+                self.verify,
+            );
+            if let Err(err) = hidden {
+                // leave no parts of the file behind
+                for carrier in &carriers {
+                    if carrier.exists() {
+                        std::fs::remove_file(carrier)?;
                     }
                 }
+                return Err(err);
+            }
+            let entry = ManifestEntry::new(
+                relative_name(out_dir, carrier),
+                relative_name(&opt.dir, &path),
+                part,
+            );
+            entries.push(match chunk {
+                Some(chunk) => entry.with_part(chunk),
+                None => entry,
+            });
+        }
+
+        // record the file before removing it, so it can always be restored
+        if opt.manifest {
+            let mut manifest = self.manifest.lock().unwrap();
+            for entry in entries {
+                manifest.insert(entry);
+            }
+            save_manifest(opt, out_dir, &manifest)?;
+        }
+        if !opt.keep_originals {
+            std::fs::remove_file(&path)?;
+        }
+        Ok(FileReport::new(path, carriers.first().cloned(), Status::Ok, None).with_parts(carriers))
+    }
+}
+
+/// Pick the image to disguise the file at `path`, or a part of it, as, that is not taken by another file or image.
+///
+/// Only base64 names can be linked to the original file, the others are chosen at random.
+/// Base64 names already taken are numbered, e.g. `YS50eHQ=.1.png`, rather than replacing the image.
+fn carrier_path(
+    naming: CarrierNaming,
+    path: &Path,
+    part: usize,
+    parts: usize,
+    taken: &Mutex<HashSet<PathBuf>>,
+) -> PathBuf {
+    let mut taken = taken.lock().unwrap();
+    for attempt in 0.. {
+        let name = match naming {
+            CarrierNaming::Plausible => {
+                // taken at any time since 2015, like a photo from a phone camera
+                let since = 1_420_070_400;
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(since, |d| d.as_secs());
+                let taken_at = UNIX_EPOCH
+                    + Duration::from_secs(rand::thread_rng().gen_range(since..=now.max(since)));
+                let digits = humantime::format_rfc3339_seconds(taken_at)
+                    .to_string()
+                    .chars()
+                    .filter(char::is_ascii_digit)
+                    .collect::<String>();
+                format!("IMG_{}_{}.png", &digits[..8], &digits[8..])
+            }
+            CarrierNaming::Random => format!("{:016x}.png", rand::random::<u64>()),
+            CarrierNaming::Base64 => {
+                let name = base64::encode(
+                    path.file_name()
+                        .unwrap_or_default()
+                        .to_string_lossy()
+                        .as_bytes(),
+                );
+                let name = match parts {
+                    1 => name,
+                    _ => format!("{}.part{}", name, part),
+                };
+                match attempt {
+                    0 => format!("{}.png", name),
+                    n => format!("{}.{}.png", name, n),
+                }
             }
+        };
+        let carrier = path.with_file_name(name);
+        if !(carrier.exists() || taken.contains(&carrier)) {
+            taken.insert(carrier.clone());
+            return carrier;
         }
     }
+    unreachable!("ran out of names for {}", path.display())
+}
+
+/// Hide a payload in a cover image written to `carrier`, checking the image reveals its `data` with `verify`
+///
+/// The cover image is requested for `key`, identifying the file and part hidden.
+fn hide_payload(
+    opt: &cli::Disguise,
+    image_client: &dyn ImageApi,
+    key: &str,
+    carrier: &Path,
+    payload: Payload,
+    data: &[u8],
+    verify: fn(&Path, &cli::EncodeOpts, usize, &[u8]) -> Result<()>,
+) -> Result<()> {
+    let width_of_img_to_request = image_api::get_square_image_width_from_bytes(payload.size());
+    let mask = image_client
+        .get_square_image_for(key, width_of_img_to_request)
+        .map_err(|err| anyhow::anyhow!("{}", err))
+        .context(format!(
+            "fetching cover image width={}",
+            width_of_img_to_request
+        ))?;
+    let mask = ImageReader::new(Cursor::new(mask))
+        .with_guessed_format()?
+        .decode()?
+        .into_rgb8();
+
+    if let Some(parent) = carrier.parent() {
+        std::fs::create_dir_all(parent)
+            .context(format!("failed to create directory {}", parent.display()))?;
+    }
+    let report = hide(
+        cli::Encode {
+            check_max_length: false,
+            opts: opt.opts.clone(),
+            input: Vec::new(),                   // not used as the payload is given
+            embed_metadata: true,                // the payload holds the metadata
+            output: Some(carrier.to_path_buf()), // where to hide
+            image: PathBuf::new(),               // not used as calling `hide` directly
+        },
+        payload.into_bytes(),
+        mask, // image to hide in
+    )?;
+    match report {
+        Report::Encode(report) => verify(carrier, &opt.opts, report.encoded_length, data),
+        _ => Ok(()),
+    }
+}
+
+/// Read back the file hidden in a disguised image, to check it reveals exactly the original data
+fn verify_carrier(
+    carrier: &Path,
+    opts: &cli::EncodeOpts,
+    encoded_length: usize,
+    data: &[u8],
+) -> Result<()> {
+    let mut opts: cli::DecodeOpts = opts.clone().into();
+    if let Some(BitDistribution::Linear { .. }) = opts.distribution {
+        opts.distribution = Some(BitDistribution::Linear {
+            length: linear_length(encoded_length),
+        });
+    }
+    let revealed = reveal(&opts, &load_rgb8_img(&carrier.to_path_buf())?)?;
+    let revealed = match revealed.payload {
+        Payload::Raw(data) | Payload::File { data, .. } | Payload::Chunk { data, .. } => data,
+        Payload::Archive(_) => Vec::new(),
+    };
+    if revealed != data {
+        return Err(StegError::Verification(format!(
+            "{} does not reveal the file hidden in it",
+            carrier.display()
+        ))
+        .into());
+    }
     Ok(())
 }
 
+/// Find the files in a directory to disguise or restore, following the options to recurse into subdirectories,
+/// follow symbolic links and include hidden files.
+///
+/// Include and exclude patterns only apply when disguising, as the names of disguised files are hidden.
+fn disguise_files(opt: &cli::Disguise, disguising: bool) -> Result<Vec<PathBuf>> {
+    let globs = |patterns: &[String]| -> Result<Option<GlobSet>> {
+        if patterns.is_empty() {
+            return Ok(None);
+        }
+        let mut builder = GlobSetBuilder::new();
+        for pattern in patterns {
+            builder.add(Glob::new(pattern).context(format!("invalid pattern {:?}", pattern))?);
+        }
+        Ok(Some(builder.build()?))
+    };
+    let include = globs(&opt.include)?;
+    let exclude = globs(&opt.exclude)?;
+    // don't disguise the cover images, cached images or output when they are inside the directory
+    let skip_dirs = [
+        opt.covers.as_ref(),
+        opt.http.cache_dir.as_ref(),
+        opt.out_dir.as_ref(),
+    ]
+    .into_iter()
+    .flatten()
+    .filter_map(|dir| dir.canonicalize().ok())
+    .collect::<Vec<_>>();
+
+    let mut walk = WalkDir::new(&opt.dir)
+        .min_depth(1)
+        .follow_links(opt.follow_symlinks)
+        .sort_by_file_name();
+    if !opt.recursive {
+        walk = walk.max_depth(1);
+    }
+    let mut files = Vec::new();
+    for dirent in walk.into_iter().filter_entry(|e| {
+        // the directory itself may be hidden, such as `.`
+        let hidden = e.depth() > 0 && e.file_name().to_string_lossy().starts_with('.');
+        opt.hidden || !hidden
+    }) {
+        let dirent = match dirent {
+            Ok(dirent) => dirent,
+            Err(err) => {
+                warn!("skipping: {}", err);
+                continue;
+            }
+        };
+        // symbolic links are only files when followed
+        if !dirent.file_type().is_file() || dirent.file_name() == MANIFEST_NAME {
+            continue;
+        }
+        let path = dirent.into_path();
+        if !skip_dirs.is_empty() {
+            if let Ok(canonical) = path.canonicalize() {
+                if skip_dirs.iter().any(|dir| canonical.starts_with(dir)) {
+                    continue;
+                }
+            }
+        }
+        if disguising {
+            let relative = path.strip_prefix(&opt.dir).unwrap_or(&path);
+            if include.as_ref().is_some_and(|g| !g.is_match(relative))
+                || exclude.as_ref().is_some_and(|g| g.is_match(relative))
+            {
+                debug!("skipping {}, filtered by pattern", path.display());
+                continue;
+            }
+        }
+        files.push(path);
+    }
+    Ok(files)
+}
+
+/// Path of a file relative to a directory, separated by `/` as recorded in a manifest
+fn relative_name(dir: &Path, path: &Path) -> String {
+    path.strip_prefix(dir)
+        .unwrap_or(path)
+        .components()
+        .filter_map(|c| match c {
+            std::path::Component::Normal(part) => Some(part.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Derive the original filename from the base64 encoded name of an image disguised by earlier versions
+fn original_fname_from_stem(path: &Path) -> Result<String> {
+    let fname = path.file_stem().unwrap_or_default().to_string_lossy();
+    let original_fname = base64::decode(fname.as_bytes())
+        .context(format!("error decoding original filename from {:?}", path))?;
+    String::from_utf8(original_fname)
+        .context(format!("error deriving original filename from {:?}", path))
+}
+
 /// Determine whether a directory entry is a hidden file (i.e. starts with a `.`)
 fn is_not_hidden(entry: &DirEntry) -> bool {
     entry
@@ -279,3 +1585,470 @@ fn is_not_hidden(entry: &DirEntry) -> bool {
         .map(|s| !s.starts_with('.'))
         .unwrap_or(false)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use structopt::StructOpt;
+
+    /// An empty directory for a test to disguise files in
+    fn test_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("stegosaurust-run-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Options to disguise the files in `dir` in generated cover images, with the extra `args` given
+    fn disguise_opts(dir: &Path, args: &[&str]) -> cli::Disguise {
+        let mut argv = vec![
+            "disguise",
+            "--generate",
+            "gradient",
+            "--generate-seed",
+            "seed",
+        ];
+        argv.extend(args);
+        argv.push(dir.to_str().unwrap());
+        cli::Disguise::from_iter(argv)
+    }
+
+    fn disguise_dir(dir: &Path, args: &[&str]) -> Vec<FileReport> {
+        hide_in_covers(&disguise_opts(dir, args), cli::Format::Json).unwrap()
+    }
+
+    fn restore_dir(dir: &Path, args: &[&str]) -> Result<Vec<FileReport>> {
+        let args = [&["--decode"], args].concat();
+        restore_disguised(&disguise_opts(dir, &args), cli::Format::Json)
+    }
+
+    /// Paths of the files in `dir` and its subdirectories relative to it, sorted
+    fn files_in(dir: &Path) -> Vec<String> {
+        let mut files = WalkDir::new(dir)
+            .into_iter()
+            .map(|e| e.unwrap())
+            .filter(|e| e.file_type().is_file())
+            .map(|e| relative_name(dir, e.path()))
+            .collect::<Vec<_>>();
+        files.sort();
+        files
+    }
+
+    /// The report of the file restored to, or disguised from, `path`
+    fn report_for<'r>(reports: &'r [FileReport], path: &Path) -> &'r FileReport {
+        reports
+            .iter()
+            .find(|r| r.path == path)
+            .unwrap_or_else(|| panic!("no report for {}", path.display()))
+    }
+
+    /// Disguise `a.txt` and `b.txt` recording them in a manifest, returning the images they were hidden in
+    fn disguise_with_manifest(dir: &Path) -> (PathBuf, PathBuf) {
+        std::fs::write(dir.join("a.txt"), "first file").unwrap();
+        std::fs::write(dir.join("b.txt"), "second file").unwrap();
+        let reports = disguise_dir(dir, &["--manifest", "--key", "secret"]);
+        let carrier = |name: &str| {
+            let report = report_for(&reports, &dir.join(name));
+            assert_eq!(report.status, Status::Ok);
+            report.carrier.clone().unwrap()
+        };
+        (carrier("a.txt"), carrier("b.txt"))
+    }
+
+    #[test]
+    fn test_restore_tampered_carrier() {
+        let dir = test_dir("tampered");
+        let (a, b) = disguise_with_manifest(&dir);
+        // the image of `a.txt` now reveals `b.txt`, whose hash differs from the manifest's
+        std::fs::copy(&b, &a).unwrap();
+
+        let reports = restore_dir(&dir, &["--key", "secret"]).unwrap();
+        let report = report_for(&reports, &a);
+        assert_eq!(report.status, Status::Tampered);
+        assert!(a.exists());
+        assert!(!dir.join("a.txt").exists());
+        assert_eq!(report_for(&reports, &dir.join("b.txt")).status, Status::Ok);
+        // the tampered image is still listed, to restore it once it is fixed
+        let manifest = load_manifest(&disguise_opts(&dir, &["--key", "secret"]), &dir)
+            .unwrap()
+            .unwrap();
+        assert_eq!(manifest.entries.len(), 1);
+        assert_eq!(manifest.entries[0].path, "a.txt");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_restore_missing_carrier() {
+        let dir = test_dir("missing");
+        let (a, _) = disguise_with_manifest(&dir);
+        std::fs::remove_file(&a).unwrap();
+
+        let reports = restore_dir(&dir, &["--key", "secret"]).unwrap();
+        let report = report_for(&reports, &dir.join("a.txt"));
+        assert_eq!(report.status, Status::Missing);
+        assert_eq!(report.carrier.as_ref(), Some(&a));
+        assert_eq!(report_for(&reports, &dir.join("b.txt")).status, Status::Ok);
+        assert_eq!(
+            std::fs::read_to_string(dir.join("b.txt")).unwrap(),
+            "second file"
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_restore_wrong_manifest_key() {
+        let dir = test_dir("wrong-key");
+        disguise_with_manifest(&dir);
+        let before = files_in(&dir);
+
+        let Err(err) = restore_dir(&dir, &["--key", "wrong"]) else {
+            panic!("restored with the wrong key");
+        };
+        assert_eq!(format!("{}", err), "reading manifest");
+        assert_eq!(report::error_code(&err), "CRYPTO_DECRYPTION");
+        // nothing is restored or removed
+        assert_eq!(files_in(&dir), before);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_disguise_files() {
+        let dir = test_dir("walk");
+        for file in [
+            "a.txt",
+            "b.pdf",
+            ".hidden.txt",
+            MANIFEST_NAME,
+            "docs/c.txt",
+            "docs/drafts/d.txt",
+            ".git/config",
+            "out/e.txt",
+        ] {
+            let path = dir.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, file).unwrap();
+        }
+        let walk = |args: &[&str], disguising: bool| {
+            disguise_files(&disguise_opts(&dir, args), disguising)
+                .unwrap()
+                .iter()
+                .map(|path| relative_name(&dir, path))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(walk(&[], true), ["a.txt", "b.pdf"]);
+        assert_eq!(
+            walk(&["--recursive"], true),
+            [
+                "a.txt",
+                "b.pdf",
+                "docs/c.txt",
+                "docs/drafts/d.txt",
+                "out/e.txt"
+            ]
+        );
+        assert_eq!(
+            walk(&["--recursive", "--hidden"], true),
+            [
+                ".git/config",
+                ".hidden.txt",
+                "a.txt",
+                "b.pdf",
+                "docs/c.txt",
+                "docs/drafts/d.txt",
+                "out/e.txt"
+            ]
+        );
+        let filtered = [
+            "--recursive",
+            "--include",
+            "*.txt",
+            "--exclude",
+            "docs/drafts/**",
+        ];
+        assert_eq!(walk(&filtered, true), ["a.txt", "docs/c.txt", "out/e.txt"]);
+        // patterns choose which files to disguise, every image is restored
+        assert_eq!(walk(&filtered, false).len(), 5);
+        let out_dir = dir.join("out");
+        assert_eq!(
+            walk(
+                &["--recursive", "--out-dir", out_dir.to_str().unwrap()],
+                true
+            ),
+            ["a.txt", "b.pdf", "docs/c.txt", "docs/drafts/d.txt"]
+        );
+        assert!(disguise_files(&disguise_opts(&dir, &["--include", "["]), true).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_disguise_out_dir() {
+        let dir = test_dir("out-dir");
+        let (src, out, restored) = (dir.join("src"), dir.join("out"), dir.join("restored"));
+        std::fs::create_dir_all(src.join("sub")).unwrap();
+        std::fs::write(src.join("a.txt"), "first file").unwrap();
+        std::fs::write(src.join("sub/b.txt"), "second file").unwrap();
+
+        let out_dir = ["--recursive", "--out-dir", out.to_str().unwrap()];
+        let reports = disguise_dir(&src, &out_dir);
+        assert!(reports.iter().all(|r| r.status == Status::Ok));
+        // each image is written where its file was relative to the output directory
+        for report in &reports {
+            let carrier = report.carrier.as_ref().unwrap();
+            let relative = report.path.strip_prefix(&src).unwrap();
+            assert_eq!(carrier.parent(), out.join(relative).parent());
+        }
+        assert!(files_in(&src).is_empty());
+        assert_eq!(files_in(&out).len(), 2);
+
+        let reports = restore_dir(
+            &out,
+            &["--recursive", "--out-dir", restored.to_str().unwrap()],
+        )
+        .unwrap();
+        assert!(reports.iter().all(|r| r.status == Status::Ok));
+        assert!(files_in(&out).is_empty());
+        assert_eq!(files_in(&restored), ["a.txt", "sub/b.txt"]);
+        assert_eq!(
+            std::fs::read_to_string(restored.join("sub/b.txt")).unwrap(),
+            "second file"
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_disguise_dry_run() {
+        let dir = test_dir("dry-run");
+        std::fs::write(dir.join("a.txt"), "first file").unwrap();
+
+        let reports = disguise_dir(&dir, &["--dry-run"]);
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].status, Status::Planned);
+        assert!(reports[0].carrier.is_some());
+        assert_eq!(files_in(&dir), ["a.txt"]);
+
+        disguise_dir(&dir, &[]);
+        let disguised = files_in(&dir);
+        let reports = restore_dir(&dir, &["--dry-run"]).unwrap();
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].status, Status::Planned);
+        assert_eq!(reports[0].path, dir.join("a.txt"));
+        assert_eq!(files_in(&dir), disguised);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_disguise_keep_originals() {
+        let dir = test_dir("keep-originals");
+        let restored = dir.join("restored");
+        std::fs::write(dir.join("a.txt"), "first file").unwrap();
+
+        let reports = disguise_dir(&dir, &["--keep-originals"]);
+        let carrier = reports[0].carrier.clone().unwrap();
+        assert!(dir.join("a.txt").exists());
+        assert!(carrier.exists());
+
+        std::fs::remove_file(dir.join("a.txt")).unwrap();
+        let reports = restore_dir(
+            &dir,
+            &["--keep-originals", "--out-dir", restored.to_str().unwrap()],
+        )
+        .unwrap();
+        assert_eq!(reports[0].status, Status::Ok);
+        assert!(carrier.exists());
+        assert_eq!(
+            std::fs::read_to_string(restored.join("a.txt")).unwrap(),
+            "first file"
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_disguise_verify_failure_keeps_original() {
+        let dir = test_dir("verify");
+        std::fs::write(dir.join("a.txt"), "first file").unwrap();
+
+        let opt = disguise_opts(&dir, &[]);
+        let mut hider = Hider::new(&opt, cli::Format::Json).unwrap();
+        hider.verify = |carrier, _, _, _| {
+            Err(StegError::Verification(format!("{} is unreadable", carrier.display())).into())
+        };
+        let reports = hider.hide(&[dir.join("a.txt")]);
+        assert_eq!(reports[0].status, Status::Failed);
+        assert_eq!(
+            reports[0].error.as_ref().map(|e| e.code),
+            Some("STEG_VERIFICATION")
+        );
+        // the original is kept, and the image that failed to verify removed
+        assert_eq!(files_in(&dir), ["a.txt"]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Disguise `data` as `big.bin`, split into parts of `chunk_size` bytes, returning the images of each part
+    fn disguise_split(dir: &Path, data: &[u8], chunk_size: usize) -> Vec<PathBuf> {
+        std::fs::write(dir.join("big.bin"), data).unwrap();
+        let opt = disguise_opts(dir, &[]);
+        let mut hider = Hider::new(&opt, cli::Format::Json).unwrap();
+        hider.chunk_size = chunk_size;
+        let reports = hider.hide(&[dir.join("big.bin")]);
+        assert_eq!(reports[0].status, Status::Ok);
+        assert!(!dir.join("big.bin").exists());
+        reports[0].parts.clone()
+    }
+
+    fn split_data() -> Vec<u8> {
+        (0..1000).map(|i| (i * 7 % 251) as u8).collect()
+    }
+
+    #[test]
+    fn test_split_file_roundtrip() {
+        let dir = test_dir("split");
+        let data = split_data();
+        let parts = disguise_split(&dir, &data, 300);
+        assert_eq!(parts.len(), 4);
+        assert!(parts.iter().all(|part| part.exists()));
+
+        let reports = restore_dir(&dir, &[]).unwrap();
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].status, Status::Ok);
+        assert_eq!(reports[0].parts.len(), 4);
+        assert_eq!(std::fs::read(dir.join("big.bin")).unwrap(), data);
+        assert_eq!(files_in(&dir), ["big.bin"]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_split_file_missing_part() {
+        let dir = test_dir("split-missing");
+        let parts = disguise_split(&dir, &split_data(), 300);
+        std::fs::remove_file(&parts[2]).unwrap();
+
+        let reports = restore_dir(&dir, &[]).unwrap();
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].status, Status::Failed);
+        assert_eq!(reports[0].path, dir.join("big.bin"));
+        assert_eq!(reports[0].parts.len(), 3);
+        // nothing is written, and the images of the parts found are kept
+        assert!(!dir.join("big.bin").exists());
+        assert_eq!(files_in(&dir).len(), 3);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_split_file_parts_out_of_order() {
+        let dir = test_dir("split-order");
+        let data = split_data();
+        let parts = disguise_split(&dir, &data, 300);
+        // name the images so the last part is revealed first
+        for (i, part) in parts.iter().rev().enumerate() {
+            std::fs::rename(part, dir.join(format!("{}.png", i))).unwrap();
+        }
+
+        let reports = restore_dir(&dir, &[]).unwrap();
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].status, Status::Ok);
+        assert_eq!(std::fs::read(dir.join("big.bin")).unwrap(), data);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_carrier_path_naming() {
+        let dir = test_dir("naming");
+        let path = dir.join("a.txt");
+        let taken = Mutex::new(HashSet::new());
+        let name = |naming, part, parts| {
+            let carrier = carrier_path(naming, &path, part, parts, &taken);
+            assert_eq!(carrier.parent(), Some(dir.as_path()));
+            carrier.file_name().unwrap().to_string_lossy().into_owned()
+        };
+
+        // like `IMG_20240112_183344.png`
+        let plausible = name(CarrierNaming::Plausible, 1, 1);
+        let digits = plausible
+            .strip_prefix("IMG_")
+            .and_then(|n| n.strip_suffix(".png"))
+            .unwrap();
+        assert_eq!(digits.len(), 15);
+        assert_eq!(&digits[8..9], "_");
+        assert!(digits.replace('_', "").chars().all(|c| c.is_ascii_digit()));
+
+        // like `3f9a1c0b7e2d4a58.png`
+        let random = name(CarrierNaming::Random, 1, 1);
+        let hex = random.strip_suffix(".png").unwrap();
+        assert_eq!(hex.len(), 16);
+        assert!(hex.chars().all(|c| c.is_ascii_hexdigit()));
+
+        assert_eq!(name(CarrierNaming::Base64, 1, 1), "YS50eHQ=.png");
+        assert_eq!(name(CarrierNaming::Base64, 2, 3), "YS50eHQ=.part2.png");
+        // taken by another file being disguised
+        assert_eq!(name(CarrierNaming::Base64, 1, 1), "YS50eHQ=.1.png");
+        // or by an image already in the directory
+        std::fs::write(dir.join("YS50eHQ=.2.png"), "image").unwrap();
+        assert_eq!(name(CarrierNaming::Base64, 1, 1), "YS50eHQ=.3.png");
+
+        assert_eq!(taken.lock().unwrap().len(), 6);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// A batch of debounced events for changes to `paths`
+    fn changes(paths: &[PathBuf]) -> DebounceEventResult {
+        Ok(paths
+            .iter()
+            .map(|path| notify_debouncer_mini::DebouncedEvent {
+                path: path.clone(),
+                kind: notify_debouncer_mini::DebouncedEventKind::Any,
+            })
+            .collect())
+    }
+
+    #[test]
+    fn test_watch_disguises_each_batch() {
+        let dir = test_dir("watch");
+        std::fs::write(dir.join("a.txt"), "first file").unwrap();
+        std::fs::write(dir.join("b.txt"), "second file").unwrap();
+        let opt = disguise_opts(&dir, &["--naming", "base64"]);
+        let hider = Hider::new(&opt, cli::Format::Json).unwrap();
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        tx.send(changes(&[dir.join("a.txt")])).unwrap();
+        tx.send(Err(notify_debouncer_mini::notify::Error::generic("lost")))
+            .unwrap();
+        // the image just written is not disguised again
+        tx.send(changes(&[dir.join("YS50eHQ=.png")])).unwrap();
+        tx.send(changes(&[dir.join("b.txt"), dir.join("gone.txt")]))
+            .unwrap();
+        drop(tx);
+
+        let mut batches = Vec::new();
+        disguise_changes(&opt, &hider, rx, |disguised| batches.push(disguised));
+        let paths = batches
+            .iter()
+            .map(|batch| batch.iter().map(|f| f.path.clone()).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        assert_eq!(paths, [[dir.join("a.txt")], [dir.join("b.txt")]]);
+        assert!(batches.iter().flatten().all(|f| f.status == Status::Ok));
+        assert_eq!(files_in(&dir), ["YS50eHQ=.png", "Yi50eHQ=.png"]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_watch_continues_after_error() {
+        let dir = test_dir("watch-error");
+        std::fs::write(dir.join("a.txt"), "first file").unwrap();
+        // the invalid pattern fails to find the files changed in every batch
+        let opt = disguise_opts(&dir, &["--include", "["]);
+        let hider = Hider::new(&opt, cli::Format::Json).unwrap();
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        tx.send(changes(&[dir.join("a.txt")])).unwrap();
+        tx.send(changes(&[dir.join("a.txt")])).unwrap();
+        drop(tx);
+
+        let mut batches = 0;
+        disguise_changes(&opt, &hider, rx, |_| batches += 1);
+        assert_eq!(batches, 0);
+        assert_eq!(files_in(&dir), ["a.txt"]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,15 +1,21 @@
-use image::{Pixel, RgbImage};
+use std::sync::Mutex;
+
+use image::RgbImage;
 use itertools_num::linspace;
 use rand::Rng;
 use rand_pcg::Pcg64;
 use rand_seeder::Seeder;
-use std::convert::From;
-use std::fmt::Write;
 
-use crate::cli::{BitDistribution, EncodeOpts, StegMethod};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::cli::EncodeOpts;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::stego::StegoBuilder;
 use crate::StegError;
 
-const END: &[u8] = b"$T3G";
+pub use stegosaurust_core::{
+    linear_length, max_len, BitDistribution, BitEncoder, BitEncoding, EmbedError, Layout, Lsb, Rsb,
+    StegMethod, END,
+};
 
 /// Behaviour to encode a message into an image and decode the message back out
 pub trait Steganography {
@@ -22,280 +28,93 @@ pub trait Steganography {
 }
 
 /// Get a steganography encoder from cli `EncodeOpts`
-pub fn encoder_from_opts(opts: EncodeOpts) -> Box<dyn Steganography> {
-    let steg_method = opts.method.unwrap_or_default();
-
-    // create encoder
-    let encoder: Box<dyn Steganography> = match &steg_method {
-        StegMethod::LeastSignificantBit => {
-            let lsb = Box::<Lsb>::default();
-            Box::new(BitEncoder::new(
-                lsb,
-                Some(opts.distribution.unwrap_or_default()),
-            ))
-        }
-        StegMethod::RandomSignificantBit => {
-            let rsb = Box::new(Rsb::new(opts.max_bit.unwrap(), &opts.seed.unwrap()));
-            Box::new(BitEncoder::new(
-                rsb,
-                Some(opts.distribution.unwrap_or_default()),
-            ))
-        }
-    };
-
-    encoder
-}
-
-/// Bit masks for setting/clearing bits in bytes.
-#[derive(Clone)]
-enum BitMask {
-    One = 0b0000_0001,
-    Two = 0b0000_0010,
-    Four = 0b0000_0100,
-    Eight = 0b0000_1000,
+#[cfg(not(target_arch = "wasm32"))]
+pub fn encoder_from_opts(opts: EncodeOpts) -> Result<Box<dyn Steganography>, StegError> {
+    Ok(Box::new(StegoBuilder::from(&opts).build()?.encoder()))
 }
 
-impl From<u8> for BitMask {
-    fn from(num: u8) -> Self {
-        match num {
-            1 => BitMask::One,
-            2 => BitMask::Two,
-            3 => BitMask::Four,
-            4 => BitMask::Eight,
-            other => panic!("cannot create bitmask from value {}", other),
-        }
-    }
-}
-
-/// Behvaiour to encode a single bit of information into a byte
-pub trait BitEncoding {
-    /// Encode a bit of information into a byte
-    fn encode(&mut self, bit: &u8, color_val: &mut u8);
-    /// Decode a bit of information from a byte
-    fn decode(&mut self, color_val: &u8) -> u8;
-}
-
-/// A `BitEncoder` is something that can perform `BitEncoding`
-pub struct BitEncoder {
-    encoder: Box<dyn BitEncoding>,
-    /// Bit distribution method to use when encoding bits
-    bit_dist: BitDistribution,
-    /// Whether or not to add token sequence at the end of encoding
-    end_sequence: bool,
-}
-
-impl BitEncoder {
-    pub fn new(encoder: Box<dyn BitEncoding>, bd: Option<BitDistribution>) -> Self {
-        BitEncoder {
-            encoder,
-            bit_dist: bd.unwrap_or_default(),
-            end_sequence: true,
-        }
+impl Steganography for BitEncoder {
+    fn max_len(&self, img: &RgbImage) -> usize {
+        max_len(img.width(), img.height())
     }
-}
 
-/// Least significant bit encoding
-///
-/// With a binary message, each bit of the message is encoded
-/// into the least significant bit of each RGB byte of each pixel.
-pub struct Lsb;
-
-impl Lsb {
-    /// Creates an new instance of `Lsb`
-    pub fn new() -> Self {
-        Lsb {}
+    fn encode(&mut self, img: &RgbImage, msg: &[u8]) -> Result<RgbImage, StegError> {
+        let mut img = img.clone();
+        self.embed(&mut img, msg)?;
+        Ok(img)
     }
-}
 
-impl Default for Lsb {
-    fn default() -> Self {
-        Lsb::new()
+    fn decode(&mut self, img: &RgbImage) -> Result<Vec<u8>, StegError> {
+        Ok(self.extract(img.as_raw())?)
     }
 }
 
-/// Random significant bit encoding
+/// Random significant bit encoding as done by stegosaurust 0.4.6 and earlier, to reveal the images it hid
 ///
-/// With a binary message, each bit of the message is encoded
-/// randomly into one of the `n` least significant bits of each RGB byte of each pixel.
-pub struct Rsb {
+/// Each bit's mask was drawn in turn from a generator seeded by the seed, so unlike [`Rsb`] the mask of a bit
+/// is only known once those of the bits before it are. Masks are drawn as bits ask for them, so bits are
+/// encoded and decoded one at a time in order, and the generator is seeded again when an earlier bit is asked for.
+pub struct LegacyRsb {
     /// The maximum significant bit to possibly set/clear when encoding (1-4)
     max: u8,
-    /// A seeded random number generator do determine which significant bit to encode to/decode from
-    rng: Pcg64,
+    seed: String,
+    /// Generator the masks are drawn from, and the index of the bit its next mask is for
+    masks: Mutex<(Pcg64, usize)>,
 }
 
-impl Rsb {
-    /// Creates an new instance of `Rsb`
-    pub fn new(max: u8, seed: &str) -> Self {
-        let rng: Pcg64 = Seeder::from(seed).make_rng();
-        Rsb { max, rng }
-    }
-
-    /// Randomly choose the next `BitMask` for encoding/decoding the next bit
-    fn next_mask(&mut self) -> BitMask {
-        let n: u8 = self.rng.gen_range(1..=self.max);
-        BitMask::from(n)
+impl LegacyRsb {
+    /// Creates an new instance of `LegacyRsb`, hiding bits in one of the `max` (1-4) least significant bits
+    pub fn new(max: u8, seed: &str) -> Result<Self, EmbedError> {
+        // validates `max` the same way
+        Rsb::new(max, seed)?;
+        Ok(LegacyRsb {
+            max,
+            seed: seed.to_string(),
+            masks: Mutex::new((Seeder::from(seed).make_rng(), 0)),
+        })
     }
-}
 
-impl BitEncoding for Rsb {
-    fn encode(&mut self, bit: &u8, color_val: &mut u8) {
-        let mask = self.next_mask();
-        if *bit == 0 {
-            *color_val &= !(mask as u8);
-        } else if *bit == 1 {
-            *color_val |= mask as u8;
+    /// The mask for encoding/decoding the bit at `index`, the `index`th drawn from the generator
+    fn mask_at(&self, index: usize) -> u8 {
+        let mut masks = self.masks.lock().unwrap_or_else(|err| err.into_inner());
+        let (rng, next) = &mut *masks;
+        if index < *next {
+            *rng = Seeder::from(self.seed.as_str()).make_rng();
+            *next = 0;
+        }
+        loop {
+            let n: u8 = rng.gen_range(1..=self.max);
+            *next += 1;
+            if *next > index {
+                return 1 << (n - 1);
+            }
         }
-    }
-
-    fn decode(&mut self, color_val: &u8) -> u8 {
-        let mask = self.next_mask();
-        let c = color_val & mask as u8;
-        u8::from(c > 0)
     }
 }
 
-impl BitEncoding for Lsb {
-    fn encode(&mut self, bit: &u8, color_val: &mut u8) {
-        if *bit == 0 {
-            *color_val &= !(BitMask::One as u8);
-        } else if *bit == 1 {
-            *color_val |= BitMask::One as u8;
+impl Clone for LegacyRsb {
+    fn clone(&self) -> Self {
+        let masks = self.masks.lock().unwrap_or_else(|err| err.into_inner());
+        LegacyRsb {
+            max: self.max,
+            seed: self.seed.clone(),
+            masks: Mutex::new(masks.clone()),
         }
     }
-
-    fn decode(&mut self, color_val: &u8) -> u8 {
-        color_val & BitMask::One as u8
-    }
 }
 
-impl Steganography for BitEncoder {
-    fn max_len(&self, img: &RgbImage) -> usize {
-        ((img.width() * img.height() * 3) as usize - (END.len() * 8)) / 8
+impl BitEncoding for LegacyRsb {
+    fn encode(&self, index: usize, bit: u8, color_val: &mut u8) {
+        let mask = self.mask_at(index);
+        *color_val = (*color_val & !mask) | (mask * (bit & 1));
     }
 
-    fn encode(&mut self, img: &RgbImage, msg: &[u8]) -> Result<RgbImage, StegError> {
-        let msg = if self.end_sequence {
-            [msg, END].concat()
-        } else {
-            msg.to_owned()
-        };
-
-        let mut binary_msg = String::with_capacity(msg.len() * 8);
-        for byte in msg {
-            let _ = write!(binary_msg, "{:08b}", byte);
-        }
-        let binary_msg: Vec<u8> = binary_msg
-            .chars()
-            .map(|c| c.to_digit(10).unwrap() as u8)
-            .collect();
-
-        let mut img = img.clone();
-
-        // generate a linear distribution from 0th to last pixel, with (number of bits to encode / 3) inbetween
-        // because in each pixel we encode 3 bits (rgb)
-        let linspace_length = (binary_msg.len() as f64 / 3.).ceil() as usize;
-        let linear_pixel_dist = get_linspace(
-            0.,
-            ((img.width() * img.height()) - 1) as f64,
-            linspace_length,
-        );
-        let mut linear_pixel_dist = linear_pixel_dist.iter();
-
-        for (ctr, chunk) in binary_msg.chunks(3).enumerate() {
-            let (x, y) = match self.bit_dist {
-                BitDistribution::Sequential => {
-                    let x = ctr as u32 % img.width();
-                    let y = ctr as u32 / img.width();
-                    (x, y)
-                }
-                BitDistribution::Linear { length: _ } => {
-                    // SAFETY: unwrap as we create a linspace distribution based on the length of the message so we know
-                    // there are enough pixels
-                    let pixel_num = linear_pixel_dist.next().unwrap();
-                    let x = *pixel_num as u32 % img.width();
-                    let y = *pixel_num as u32 / img.width();
-                    (x, y)
-                }
-            };
-            let pixel = img.get_pixel_mut(x, y);
-            for (idx, bit) in chunk.iter().enumerate() {
-                self.encoder.encode(bit, &mut pixel[idx]);
-            }
-        }
-
-        if let BitDistribution::Linear { length: _ } = self.bit_dist {
-            println!(
-                "Note: use length '{}' when decoding with linear distribution",
-                linspace_length
-            );
-        }
-        Ok(img)
+    fn decode(&self, index: usize, color_val: u8) -> u8 {
+        u8::from(color_val & self.mask_at(index) > 0)
     }
 
-    fn decode(&mut self, img: &RgbImage) -> Result<Vec<u8>, StegError> {
-        let mut bitstream: Vec<u8> = Vec::new();
-
-        let mut endstream = String::new();
-        for byte in END {
-            let _ = write!(endstream, "{:08b}", byte);
-        }
-
-        let end = endstream
-            .chars()
-            .map(|c| c.to_digit(10).unwrap() as u8)
-            .collect::<Vec<u8>>();
-
-        match self.bit_dist {
-            BitDistribution::Sequential => {
-                'outer_seq: for (_, _, pixel) in img.enumerate_pixels() {
-                    for value in pixel.channels() {
-                        if has_end(&bitstream, &end) {
-                            break 'outer_seq;
-                        }
-                        bitstream.push(self.encoder.decode(value));
-                    }
-                }
-            }
-            BitDistribution::Linear { length } => {
-                let linear_pixel_dist =
-                    get_linspace(0., ((img.width() * img.height()) - 1) as f64, length);
-                'outer_lin: for pixel_num in linear_pixel_dist {
-                    let x = pixel_num as u32 % img.width();
-                    let y = pixel_num as u32 / img.width();
-                    let pixel = img.get_pixel(x, y);
-                    for value in pixel.channels() {
-                        if has_end(&bitstream, &end) {
-                            break 'outer_lin;
-                        }
-                        bitstream.push(self.encoder.decode(value));
-                    }
-                }
-            }
-        }
-
-        if self.end_sequence {
-            if !has_end(&bitstream, &end) {
-                return Err(StegError::EncodingNotFound);
-            }
-
-            // message found in the bitstream, remove the END indicator
-            bitstream.truncate(bitstream.len() - end.len());
-        }
-        let mut msg = Vec::new();
-        for chrs in bitstream.chunks(8) {
-            let binval = u8::from_str_radix(
-                &chrs
-                    .iter()
-                    .map(|c| format! {"{}",c})
-                    .collect::<Vec<String>>()
-                    .join(""),
-                2,
-            )
-            .map_err(|e| StegError::Decoding(format!("reconstructing byte: {}", e)))?;
-            msg.push(binval);
-        }
-        Ok(msg)
+    fn sequential(&self) -> bool {
+        true
     }
 }
 
@@ -312,15 +131,7 @@ impl Steganography for BitEncoder {
 /// assert_eq!(has_end(&bytes_2, &end), false);
 /// ```
 pub fn has_end(bytes: &[u8], end: &[u8]) -> bool {
-    bytes
-        .iter()
-        .rev()
-        .take(end.len())
-        .rev()
-        .copied()
-        .collect::<Vec<u8>>()
-        .iter()
-        .eq(end.iter())
+    bytes.ends_with(end)
 }
 
 /// wrapper around `itertools_num::linspace` to get a linear distribution of `n` `usize` numbers between two numbers
@@ -352,40 +163,19 @@ mod tests {
     #[test]
     fn test_rsb_steganography() {
         let img = RgbImage::new(32, 32);
-        let rsb_enc = Box::new(Rsb::new(2, "seed"));
+        let rsb_enc = Box::new(Rsb::new(2, "seed").unwrap());
         let mut enc: Box<dyn Steganography> = Box::from(BitEncoder::new(rsb_enc, None));
-        let rsb_dec = Box::new(Rsb::new(2, "seed"));
+        let rsb_dec = Box::new(Rsb::new(2, "seed").unwrap());
         let mut dec: Box<dyn Steganography> = Box::from(BitEncoder::new(rsb_dec, None));
         let secret_message = "🦕 hiding text!".as_bytes();
         let encoded: RgbImage = enc.encode(&img, secret_message).unwrap();
         assert_eq!(dec.decode(&encoded).unwrap(), secret_message);
     }
 
-    #[test]
-    fn test_rsb_random_determined_from_seed() {
-        let mut rsb1 = Rsb::new(2, "seed");
-        let mut rsb2 = Rsb::new(2, "seed");
-        for _ in 0..10 {
-            assert_eq!(rsb1.rng.gen::<u8>(), rsb2.rng.gen::<u8>());
-        }
-    }
-
-    #[test]
-    fn test_rsb_random_determined_from_seed_different() {
-        let mut rsb1 = Rsb::new(2, "seed");
-        let mut rsb2 = Rsb::new(2, "seeb");
-        let it = 1000;
-        let mut matches = Vec::with_capacity(it);
-        for _ in 0..it {
-            matches.push(rsb1.rng.gen::<u8>() == rsb2.rng.gen::<u8>());
-        }
-        assert!(matches.contains(&false));
-    }
-
     #[test]
     fn test_rsb_1_decrypts_with_lsb() {
         let img = RgbImage::new(32, 32);
-        let rsb = Box::new(Rsb::new(1, "seed"));
+        let rsb = Box::new(Rsb::new(1, "seed").unwrap());
         let mut rsb_enc: Box<dyn Steganography> = Box::from(BitEncoder::new(rsb, None));
         let lsb = Box::<Lsb>::default();
         let mut lsb_enc: Box<dyn Steganography> = Box::from(BitEncoder::new(lsb, None));
@@ -398,7 +188,7 @@ mod tests {
     #[test]
     fn test_rsb_3_not_decrypts_with_lsb() {
         let img = RgbImage::new(32, 32);
-        let rsb = Box::new(Rsb::new(3, "seed"));
+        let rsb = Box::new(Rsb::new(3, "seed").unwrap());
         let mut rsb_enc: Box<dyn Steganography> = Box::from(BitEncoder::new(rsb, None));
         let lsb = Box::<Lsb>::default();
         let mut lsb_enc: Box<dyn Steganography> = Box::from(BitEncoder::new(lsb, None));
@@ -411,6 +201,32 @@ mod tests {
         assert_eq!(result.unwrap_err(), StegError::EncodingNotFound);
     }
 
+    #[test]
+    fn test_legacy_rsb_reveals_sequentially_drawn_masks() {
+        // hide a message as stegosaurust 0.4.6 did, drawing a mask for each bit in turn
+        let mut img = RgbImage::new(32, 32);
+        let msg = ["🦕 hiding text!".as_bytes(), END].concat();
+        let mut rng: Pcg64 = Seeder::from("seed").make_rng();
+        for (index, value) in img.iter_mut().take(msg.len() * 8).enumerate() {
+            let bit = (msg[index / 8] >> (7 - index % 8)) & 1;
+            let mask = 1 << (rng.gen_range(1..=3u8) - 1);
+            *value = (*value & !mask) | (mask * bit);
+        }
+
+        let legacy = Box::new(LegacyRsb::new(3, "seed").unwrap());
+        let mut dec: Box<dyn Steganography> = Box::from(BitEncoder::new(legacy, None));
+        assert_eq!(dec.decode(&img).unwrap(), "🦕 hiding text!".as_bytes());
+        // the masks are drawn again from the start for another image
+        assert_eq!(dec.decode(&img).unwrap(), "🦕 hiding text!".as_bytes());
+        let rsb = Box::new(Rsb::new(3, "seed").unwrap());
+        let mut dec: Box<dyn Steganography> = Box::from(BitEncoder::new(rsb, None));
+        assert!(dec.decode(&img).is_err());
+        assert!(matches!(
+            LegacyRsb::new(5, "seed"),
+            Err(EmbedError::InvalidConfig(_))
+        ));
+    }
+
     #[test]
     fn test_linear_distribution_encoding() {
         let mut img = RgbImage::new(4, 4);
@@ -426,11 +242,10 @@ mod tests {
 
         // encode one byte of all ones with linear distribution
         let lsb = Box::<Lsb>::default();
-        let mut lsb_enc: Box<dyn Steganography> = Box::from(BitEncoder {
-            encoder: lsb,
-            bit_dist: BitDistribution::Linear { length: 0 },
-            end_sequence: false,
-        });
+        let mut lsb_enc: Box<dyn Steganography> = Box::from(
+            BitEncoder::new(lsb, Some(BitDistribution::Linear { length: 0 }))
+                .with_end_sequence(false),
+        );
         let new_img = lsb_enc.encode(&img, b"\xFF").unwrap();
 
         // had:
@@ -468,31 +283,55 @@ mod tests {
 
         // encode one byte of all ones with linear distribution
         let lsb = Box::<Lsb>::default();
-        let mut lsb_enc: Box<dyn Steganography> = Box::from(BitEncoder {
-            encoder: lsb,
-            bit_dist: BitDistribution::Linear { length: 0 },
-            end_sequence: false,
-        });
+        let mut lsb_enc: Box<dyn Steganography> = Box::from(
+            BitEncoder::new(lsb, Some(BitDistribution::Linear { length: 0 }))
+                .with_end_sequence(false),
+        );
         let new_img = lsb_enc.encode(&img, b"\xFF").unwrap();
 
         let lsb = Box::<Lsb>::default();
-        let mut lsb_dec: Box<dyn Steganography> = Box::from(BitEncoder {
-            encoder: lsb,
-            bit_dist: BitDistribution::Linear { length: 3 },
-            end_sequence: false,
-        });
+        let mut lsb_dec: Box<dyn Steganography> = Box::from(
+            BitEncoder::new(lsb, Some(BitDistribution::Linear { length: 3 }))
+                .with_end_sequence(false),
+        );
 
         let result = lsb_dec.decode(&new_img).unwrap();
         assert_eq!(result[0], 255);
 
         let lsb = Box::<Lsb>::default();
-        let mut lsb_dec: Box<dyn Steganography> = Box::from(BitEncoder {
-            encoder: lsb,
-            bit_dist: BitDistribution::Linear { length: 4 },
-            end_sequence: false,
-        });
+        let mut lsb_dec: Box<dyn Steganography> = Box::from(
+            BitEncoder::new(lsb, Some(BitDistribution::Linear { length: 4 }))
+                .with_end_sequence(false),
+        );
 
         let result = lsb_dec.decode(&new_img).unwrap();
         assert_ne!(result[0], 255);
     }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[test]
+    fn test_encoder_from_invalid_opts() {
+        let opts = |seed: Option<&str>, max_bit| EncodeOpts {
+            decode: false,
+            base64: false,
+            compress: false,
+            key: None,
+            method: Some(StegMethod::RandomSignificantBit),
+            distribution: None,
+            seed: seed.map(String::from),
+            max_bit,
+            legacy_rsb: false,
+        };
+        assert!(encoder_from_opts(opts(Some("seed"), Some(2))).is_ok());
+        for (seed, max_bit) in [
+            (None, Some(2)),
+            (Some("seed"), None),
+            (Some("seed"), Some(5)),
+        ] {
+            assert!(matches!(
+                encoder_from_opts(opts(seed, max_bit)),
+                Err(StegError::InvalidConfig(_))
+            ));
+        }
+    }
 }
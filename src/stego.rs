@@ -0,0 +1,322 @@
+use image::RgbImage;
+
+use crate::compress::{compress, decompress};
+use crate::crypto;
+use crate::steganography::{max_len, BitDistribution, BitEncoder, LegacyRsb, Lsb, Rsb, StegMethod};
+use crate::StegError;
+
+/// Hides payloads in images and reveals them, transforming them on the way.
+///
+/// Payloads are compressed, encrypted then base64 encoded before they are hidden, as enabled on the
+/// [`StegoBuilder`], and revealed by undoing each transformation in reverse.
+///
+/// ```rust
+/// use stegosaurust::steganography::StegMethod;
+/// use stegosaurust::stego::Stego;
+///
+/// let cover = image::RgbImage::new(100, 100);
+/// let stego = Stego::builder()
+///     .method(StegMethod::RandomSignificantBit)
+///     .max_bit(2)
+///     .seed("seed")
+///     .compress(true)
+///     .encrypt("secret")
+///     .build()
+///     .unwrap();
+///
+/// let image = stego.hide(&cover, b"hello world").unwrap();
+/// assert_eq!(stego.reveal(&image).unwrap(), b"hello world");
+/// ```
+#[derive(Clone)]
+pub struct Stego {
+    encoding: Encoding,
+    distribution: BitDistribution,
+    compress: bool,
+    key: Option<Vec<u8>>,
+    base64: bool,
+}
+
+/// Configures a [`Stego`], hiding payloads in the least significant bits, sequentially, without transforming them by default.
+#[derive(Clone, Default)]
+pub struct StegoBuilder {
+    method: StegMethod,
+    distribution: BitDistribution,
+    seed: Option<String>,
+    max_bit: Option<u8>,
+    legacy_rsb: bool,
+    compress: bool,
+    key: Option<Vec<u8>>,
+    base64: bool,
+}
+
+/// Bit encoding to hide bits with
+#[derive(Clone)]
+enum Encoding {
+    Lsb,
+    Rsb(Rsb),
+    LegacyRsb(LegacyRsb),
+}
+
+/// A payload revealed from an image, along with how it was hidden
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Revealed {
+    /// The payload, with every transformation undone
+    pub data: Vec<u8>,
+    /// Length of the data as it was hidden in the image
+    pub encoded_len: usize,
+    /// Whether the hidden data looked encrypted
+    pub encrypted: bool,
+    /// Whether the hidden data looked compressed once decrypted
+    pub compressed: bool,
+}
+
+impl StegoBuilder {
+    /// Steganography method to hide bits with
+    pub fn method(mut self, method: StegMethod) -> Self {
+        self.method = method;
+        self
+    }
+
+    /// How to distribute bits in the image.
+    ///
+    /// Revealing a payload hidden with a linear distribution needs its length, given by [`crate::steganography::linear_length`].
+    pub fn distribution(mut self, distribution: BitDistribution) -> Self {
+        self.distribution = distribution;
+        self
+    }
+
+    /// Seed choosing the bit each bit is hidden in, required for random significant bit encoding
+    pub fn seed(mut self, seed: impl Into<String>) -> Self {
+        self.seed = Some(seed.into());
+        self
+    }
+
+    /// Maximum significant bit (1-4) to hide bits in, required for random significant bit encoding
+    pub fn max_bit(mut self, max_bit: u8) -> Self {
+        self.max_bit = Some(max_bit);
+        self
+    }
+
+    /// Choose the bits of random significant bit encoding as stegosaurust 0.4.6 and earlier did,
+    /// to reveal payloads it hid
+    pub fn legacy_rsb(mut self, legacy_rsb: bool) -> Self {
+        self.legacy_rsb = legacy_rsb;
+        self
+    }
+
+    /// Compress payloads before hiding them
+    pub fn compress(mut self, compress: bool) -> Self {
+        self.compress = compress;
+        self
+    }
+
+    /// Encrypt payloads with AES-256-CBC using `key` before hiding them
+    pub fn encrypt(mut self, key: impl AsRef<[u8]>) -> Self {
+        self.key = Some(key.as_ref().to_vec());
+        self
+    }
+
+    /// Base64 encode payloads before hiding them
+    pub fn base64(mut self, base64: bool) -> Self {
+        self.base64 = base64;
+        self
+    }
+
+    /// Validate the configuration and build the [`Stego`]
+    pub fn build(self) -> Result<Stego, StegError> {
+        let encoding = match self.method {
+            StegMethod::LeastSignificantBit => Encoding::Lsb,
+            StegMethod::RandomSignificantBit => {
+                let seed = self.seed.ok_or_else(|| {
+                    StegError::InvalidConfig("a seed is required for rsb".to_string())
+                })?;
+                let max_bit = self.max_bit.ok_or_else(|| {
+                    StegError::InvalidConfig("a max bit is required for rsb".to_string())
+                })?;
+                if self.legacy_rsb {
+                    Encoding::LegacyRsb(LegacyRsb::new(max_bit, &seed)?)
+                } else {
+                    Encoding::Rsb(Rsb::new(max_bit, &seed)?)
+                }
+            }
+        };
+        Ok(Stego {
+            encoding,
+            distribution: self.distribution,
+            compress: self.compress,
+            key: self.key,
+            base64: self.base64,
+        })
+    }
+}
+
+impl Stego {
+    /// Start configuring a `Stego`
+    pub fn builder() -> StegoBuilder {
+        StegoBuilder::default()
+    }
+
+    /// The bit encoder hiding and revealing data in images
+    pub fn encoder(&self) -> BitEncoder {
+        let dist = Some(self.distribution.clone());
+        match &self.encoding {
+            Encoding::Lsb => BitEncoder::new(Box::<Lsb>::default(), dist),
+            Encoding::Rsb(rsb) => BitEncoder::new(Box::new(rsb.clone()), dist),
+            Encoding::LegacyRsb(rsb) => BitEncoder::new(Box::new(rsb.clone()), dist),
+        }
+    }
+
+    /// Maximum length of data, once transformed, that can be hidden in `cover`
+    pub fn capacity(&self, cover: &RgbImage) -> usize {
+        max_len(cover.width(), cover.height())
+    }
+
+    /// Transform a payload as it is hidden: compress, encrypt then base64 encode it.
+    pub fn pack(&self, payload: &[u8]) -> Result<Vec<u8>, StegError> {
+        let mut data = payload.to_vec();
+        if self.compress {
+            data = compress(&data)?;
+        }
+        if let Some(key) = &self.key {
+            data = crypto::encrypt(&data, key)?;
+        }
+        if self.base64 {
+            data = base64::encode(&data).into_bytes();
+        }
+        Ok(data)
+    }
+
+    /// Hide data already transformed with [`Stego::pack`] in a copy of `cover`
+    pub fn embed(&self, cover: &RgbImage, data: &[u8]) -> Result<RgbImage, StegError> {
+        let mut image = cover.clone();
+        self.encoder().embed(&mut image, data)?;
+        Ok(image)
+    }
+
+    /// Hide a payload in a copy of `cover`, transforming it first.
+    ///
+    /// Returns [`StegError::Encoding`] if the transformed payload does not fit in the image.
+    pub fn hide(&self, cover: &RgbImage, payload: &[u8]) -> Result<RgbImage, StegError> {
+        self.embed(cover, &self.pack(payload)?)
+    }
+
+    /// Undo the transformations of [`Stego::pack`] on data revealed with [`BitEncoder::extract`]
+    pub fn unpack(&self, data: Vec<u8>) -> Result<Vec<u8>, StegError> {
+        Ok(self.unpack_details(data)?.data)
+    }
+
+    /// Reveal the payload hidden in an image, along with how it was hidden
+    pub fn reveal_details(&self, image: &RgbImage) -> Result<Revealed, StegError> {
+        self.unpack_details(self.encoder().extract(image)?)
+    }
+
+    fn unpack_details(&self, mut data: Vec<u8>) -> Result<Revealed, StegError> {
+        let encoded_len = data.len();
+        if self.base64 {
+            data = base64::decode(&data)?;
+        }
+        let encrypted = data.starts_with(b"Salted__");
+        if let Some(key) = &self.key {
+            data = crypto::decrypt(&data, key)?;
+        }
+        let compressed = data.starts_with(b"BZh");
+        if self.compress {
+            data = decompress(&data)?;
+        }
+        Ok(Revealed {
+            data,
+            encoded_len,
+            encrypted,
+            compressed,
+        })
+    }
+
+    /// Reveal the payload hidden in an image, undoing each transformation applied when it was hidden
+    pub fn reveal(&self, image: &RgbImage) -> Result<Vec<u8>, StegError> {
+        Ok(self.reveal_details(image)?.data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::steganography::linear_length;
+
+    #[test]
+    fn test_hide_reveal_every_transformation() {
+        let cover = RgbImage::new(100, 100);
+        let msg = b"hello world, hello world, hello world";
+        for (compress, key, base64) in [
+            (false, None, false),
+            (true, None, false),
+            (false, Some("secret"), false),
+            (true, Some("secret"), true),
+        ] {
+            let mut builder = Stego::builder().compress(compress).base64(base64);
+            if let Some(key) = key {
+                builder = builder.encrypt(key);
+            }
+            let stego = builder.build().unwrap();
+            let image = stego.hide(&cover, msg).unwrap();
+            let revealed = stego.reveal_details(&image).unwrap();
+            assert_eq!(revealed.data, msg);
+            assert_eq!(revealed.encoded_len, stego.pack(msg).unwrap().len());
+            assert_eq!(revealed.encrypted, key.is_some());
+            assert_eq!(revealed.compressed, compress);
+        }
+    }
+
+    #[test]
+    fn test_hide_reveal_rsb_linear() {
+        let cover = RgbImage::new(100, 100);
+        let msg = b"hello world";
+        let build = |distribution| {
+            Stego::builder()
+                .method(StegMethod::RandomSignificantBit)
+                .seed("seed")
+                .max_bit(3)
+                .distribution(distribution)
+                .build()
+                .unwrap()
+        };
+        let image = build(BitDistribution::Linear { length: 0 })
+            .hide(&cover, msg)
+            .unwrap();
+        let length = linear_length(msg.len());
+        assert_eq!(
+            build(BitDistribution::Linear { length })
+                .reveal(&image)
+                .unwrap(),
+            msg
+        );
+    }
+
+    #[test]
+    fn test_build_invalid_config() {
+        let rsb = || Stego::builder().method(StegMethod::RandomSignificantBit);
+        assert!(matches!(
+            rsb().max_bit(2).build(),
+            Err(StegError::InvalidConfig(_))
+        ));
+        assert!(matches!(
+            rsb().seed("seed").build(),
+            Err(StegError::InvalidConfig(_))
+        ));
+        assert!(matches!(
+            rsb().seed("seed").max_bit(5).build(),
+            Err(StegError::InvalidConfig(_))
+        ));
+        assert!(Stego::builder().max_bit(5).build().is_ok());
+    }
+
+    #[test]
+    fn test_hide_too_long() {
+        let cover = RgbImage::new(10, 10);
+        let stego = Stego::builder().build().unwrap();
+        let msg = vec![0; stego.capacity(&cover) + 1];
+        assert!(matches!(
+            stego.hide(&cover, &msg),
+            Err(StegError::Encoding(_))
+        ));
+    }
+}
@@ -0,0 +1,301 @@
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+
+use image::RgbImage;
+
+use crate::steganography::{BitDistribution, BitEncoder, Layout, END};
+use crate::StegError;
+
+/// Hides bytes in an image as they are written.
+///
+/// Bytes are hidden exactly as [`BitEncoder::embed`] would hide them all at once, so they can be revealed with
+/// [`BitEncoder::extract`] or a [`StegReader`], and the end sequence is hidden after them by [`StegWriter::finish`].
+///
+/// ```rust
+/// use std::io::{Read, Write};
+/// use stegosaurust::stego::Stego;
+/// use stegosaurust::stream::{StegReader, StegWriter};
+///
+/// let stego = Stego::builder().build().unwrap();
+/// let mut writer = StegWriter::new(stego.encoder(), image::RgbImage::new(100, 100)).unwrap();
+/// std::io::copy(&mut &b"hello world"[..], &mut writer).unwrap();
+/// let image = writer.finish();
+///
+/// let mut message = String::new();
+/// StegReader::new(stego.encoder(), &image)
+///     .unwrap()
+///     .read_to_string(&mut message)
+///     .unwrap();
+/// assert_eq!(message, "hello world");
+/// ```
+pub struct StegWriter {
+    image: RgbImage,
+    encoder: BitEncoder,
+    layout: Layout,
+    /// Index of the next bit to hide
+    index: usize,
+}
+
+impl StegWriter {
+    /// Hide bytes written in `cover` with `encoder`.
+    ///
+    /// Streaming with a linear distribution needs the length of the message, given by [`crate::steganography::linear_length`].
+    pub fn new(encoder: BitEncoder, cover: RgbImage) -> Result<Self, StegError> {
+        let layout = layout(&encoder, &cover)?;
+        Ok(StegWriter {
+            image: cover,
+            encoder,
+            layout,
+            index: 0,
+        })
+    }
+
+    /// Number of bytes that can still be written to the image
+    pub fn remaining(&self) -> usize {
+        let reserved = if self.encoder.end_sequence() {
+            END.len()
+        } else {
+            0
+        };
+        (self.layout.bits() / 8).saturating_sub(reserved + self.index / 8)
+    }
+
+    /// Hide the end sequence after the bytes written, and get the image back
+    pub fn finish(mut self) -> RgbImage {
+        if self.encoder.end_sequence() {
+            for &byte in END {
+                self.hide_byte(byte);
+            }
+        }
+        self.image
+    }
+
+    fn hide_byte(&mut self, byte: u8) {
+        for i in 0..8 {
+            let index = self.index + i;
+            let bit = (byte >> (7 - i)) & 1;
+            let color_val = &mut (*self.image)[self.layout.position(index)];
+            self.encoder.encoding().encode(index, bit, color_val);
+        }
+        self.index += 8;
+    }
+}
+
+impl Write for StegWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = buf.len().min(self.remaining());
+        if len == 0 && !buf.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::WriteZero,
+                StegError::Encoding("no space left in the image".to_string()),
+            ));
+        }
+        for &byte in &buf[..len] {
+            self.hide_byte(byte);
+        }
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Reveals the bytes hidden in an image as they are read, up to the end sequence.
+///
+/// Only the bits of the bytes read, and of the few after them needed to spot the end sequence, are revealed.
+/// Reading fails with [`io::ErrorKind::InvalidData`] if the image ends before the end sequence is found.
+pub struct StegReader<'a> {
+    image: &'a RgbImage,
+    encoder: BitEncoder,
+    layout: Layout,
+    /// Index of the next bit to reveal
+    index: usize,
+    /// Bytes revealed but not read yet, held back until they are known not to be the end sequence
+    pending: VecDeque<u8>,
+    /// Whether the end of the message has been read
+    done: bool,
+}
+
+impl<'a> StegReader<'a> {
+    /// Reveal the bytes hidden in `image` with `encoder`.
+    ///
+    /// Streaming with a linear distribution needs the length of the message, given by [`crate::steganography::linear_length`].
+    pub fn new(encoder: BitEncoder, image: &'a RgbImage) -> Result<Self, StegError> {
+        let layout = layout(&encoder, image)?;
+        Ok(StegReader {
+            image,
+            encoder,
+            layout,
+            index: 0,
+            pending: VecDeque::with_capacity(END.len()),
+            done: false,
+        })
+    }
+
+    /// Reveal the next byte, or only the bits left if there are fewer than 8
+    fn reveal_byte(&mut self) -> Option<u8> {
+        if self.index >= self.layout.bits() {
+            return None;
+        }
+        let end = (self.index + 8).min(self.layout.bits());
+        let byte = (self.index..end).fold(0, |byte, index| {
+            let color_val = self.image.as_raw()[self.layout.position(index)];
+            (byte << 1) | self.encoder.encoding().decode(index, color_val)
+        });
+        self.index = end;
+        Some(byte)
+    }
+}
+
+impl Read for StegReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let lookahead = if self.encoder.end_sequence() {
+            END.len()
+        } else {
+            1
+        };
+        let mut n = 0;
+        while n < buf.len() && !self.done {
+            while self.pending.len() < lookahead {
+                match self.reveal_byte() {
+                    Some(byte) => self.pending.push_back(byte),
+                    None => break,
+                }
+            }
+            if self.encoder.end_sequence() {
+                if self.pending.iter().eq(END) {
+                    self.done = true;
+                    break;
+                }
+                if self.pending.len() < END.len() {
+                    // return what was read, failing on the next read
+                    if n > 0 {
+                        break;
+                    }
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        StegError::EncodingNotFound,
+                    ));
+                }
+            }
+            match self.pending.pop_front() {
+                Some(byte) => {
+                    buf[n] = byte;
+                    n += 1;
+                }
+                None => self.done = true,
+            }
+        }
+        Ok(n)
+    }
+}
+
+/// Lay out the bits of a message streamed to or from an image
+fn layout(encoder: &BitEncoder, image: &RgbImage) -> Result<Layout, StegError> {
+    let pixels = image.as_raw().len() / 3;
+    match *encoder.distribution() {
+        BitDistribution::Linear { length: 0 } => Err(StegError::InvalidConfig(
+            "streaming with a linear distribution needs the length of the message".to_string(),
+        )),
+        BitDistribution::Linear { length } if length > pixels => {
+            Err(StegError::InvalidConfig(format!(
+                "a linear distribution over {} pixels does not fit in {} pixels",
+                length, pixels
+            )))
+        }
+        BitDistribution::Linear { length } => {
+            Ok(Layout::new(encoder.distribution(), pixels, length))
+        }
+        BitDistribution::Sequential => Ok(Layout::new(encoder.distribution(), pixels, 0)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::steganography::{linear_length, Lsb, Rsb};
+
+    fn encoders(msg_len: usize) -> Vec<BitEncoder> {
+        let linear = BitDistribution::Linear {
+            length: linear_length(msg_len),
+        };
+        vec![
+            BitEncoder::new(Box::<Lsb>::default(), None),
+            BitEncoder::new(Box::<Lsb>::default(), Some(linear.clone())),
+            BitEncoder::new(Box::new(Rsb::new(3, "seed").unwrap()), None),
+            BitEncoder::new(Box::new(Rsb::new(3, "seed").unwrap()), Some(linear)),
+        ]
+    }
+
+    #[test]
+    fn test_stream_matches_embed_and_extract() {
+        let cover = RgbImage::from_fn(64, 64, |x, y| image::Rgb([x as u8, y as u8, 7]));
+        let msg = (0..1000).map(|i| (i * 7) as u8).collect::<Vec<u8>>();
+        for (streamed, whole) in encoders(msg.len()).into_iter().zip(encoders(msg.len())) {
+            let mut writer = StegWriter::new(streamed, cover.clone()).unwrap();
+            // write in uneven pieces
+            for piece in msg.chunks(333) {
+                writer.write_all(piece).unwrap();
+            }
+            let image = writer.finish();
+
+            let mut expected = cover.clone();
+            whole.embed(&mut expected, &msg).unwrap();
+            assert!(image == expected);
+
+            let mut reader = StegReader::new(whole, &image).unwrap();
+            let mut revealed = Vec::new();
+            let mut buf = [0; 7];
+            loop {
+                match reader.read(&mut buf).unwrap() {
+                    0 => break,
+                    n => revealed.extend_from_slice(&buf[..n]),
+                }
+            }
+            assert_eq!(revealed, msg);
+        }
+    }
+
+    #[test]
+    fn test_writer_full() {
+        let encoder = BitEncoder::new(Box::<Lsb>::default(), None);
+        let mut writer = StegWriter::new(encoder, RgbImage::new(8, 8)).unwrap();
+        assert_eq!(writer.remaining(), 8 * 8 * 3 / 8 - END.len());
+        let err = writer.write_all(&[1; 100]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WriteZero);
+        assert_eq!(writer.remaining(), 0);
+    }
+
+    #[test]
+    fn test_reader_without_message() {
+        let image = RgbImage::new(8, 8);
+        let encoder = BitEncoder::new(Box::<Lsb>::default(), None);
+        let err = StegReader::new(encoder, &image)
+            .unwrap()
+            .read_to_end(&mut Vec::new())
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_linear_needs_length() {
+        let image = RgbImage::new(8, 8);
+        for length in [0, 65] {
+            let linear = || {
+                BitEncoder::new(
+                    Box::<Lsb>::default(),
+                    Some(BitDistribution::Linear { length }),
+                )
+            };
+            assert!(matches!(
+                StegWriter::new(linear(), image.clone()),
+                Err(StegError::InvalidConfig(_))
+            ));
+            assert!(matches!(
+                StegReader::new(linear(), &image),
+                Err(StegError::InvalidConfig(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,216 @@
+use std::io::Cursor;
+
+use image::ImageOutputFormat;
+use wasm_bindgen::prelude::*;
+
+use crate::payload::Payload;
+use crate::stego::Stego;
+use crate::StegError;
+
+/// How to hide and reveal a message, as the options of `stegosaurust encode` and `decode`.
+///
+/// ```js
+/// const options = new Options();
+/// options.method = "rsb";
+/// options.seed = "seed";
+/// options.maxBit = 2;
+/// options.key = "secret";
+/// const { image } = hide(cover, message, options);
+/// ```
+#[wasm_bindgen(getter_with_clone)]
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// Method to hide bits with, `lsb` (default) or `rsb`
+    pub method: Option<String>,
+    /// Distribution of the bits in the image, `sequential` (default), `linear` or `linear-N` to reveal
+    pub distribution: Option<String>,
+    /// Seed for random significant bit encoding
+    pub seed: Option<String>,
+    /// Maximum bit (1-4) to hide bits in with random significant bit encoding
+    #[wasm_bindgen(js_name = maxBit)]
+    pub max_bit: Option<u8>,
+    /// Compress the message
+    pub compress: bool,
+    /// Key to encrypt the message with using AES-256-CBC
+    pub key: Option<String>,
+    /// Base64 encode the message
+    pub base64: bool,
+}
+
+#[wasm_bindgen]
+impl Options {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Options {
+    fn stego(&self) -> Result<Stego, StegError> {
+        let mut builder = Stego::builder().compress(self.compress).base64(self.base64);
+        if let Some(method) = &self.method {
+            builder = builder.method(method.parse()?);
+        }
+        if let Some(distribution) = &self.distribution {
+            builder = builder.distribution(distribution.parse()?);
+        }
+        if let Some(seed) = &self.seed {
+            builder = builder.seed(seed);
+        }
+        if let Some(max_bit) = self.max_bit {
+            builder = builder.max_bit(max_bit);
+        }
+        if let Some(key) = &self.key {
+            builder = builder.encrypt(key);
+        }
+        builder.build()
+    }
+}
+
+/// An image a message was hidden in, with the lengths to reveal it
+#[wasm_bindgen(getter_with_clone)]
+#[derive(Debug, Clone)]
+pub struct Hidden {
+    /// The image as PNG bytes
+    pub image: Vec<u8>,
+    /// Length of the message once compressed, encrypted and base64 encoded, as hidden in the image
+    #[wasm_bindgen(js_name = encodedLen)]
+    pub encoded_len: usize,
+    /// Length to reveal the message with when hidden with a `linear` distribution, as `linear-N`
+    #[wasm_bindgen(js_name = linearLength)]
+    pub linear_length: usize,
+}
+
+/// Hide a message in a cover image, in any format `image` can read, returning the image as PNG bytes with the
+/// lengths to reveal it
+#[wasm_bindgen]
+pub fn hide(cover: &[u8], message: &[u8], options: &Options) -> Result<Hidden, JsError> {
+    let cover = image::load_from_memory(cover)?.into_rgb8();
+    let stego = options.stego()?;
+    let data = stego.pack(message)?;
+    let image = stego.embed(&cover, &data)?;
+    let mut png = Vec::new();
+    image.write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)?;
+    Ok(Hidden {
+        image: png,
+        encoded_len: data.len(),
+        linear_length: linear_length(data.len()),
+    })
+}
+
+/// Reveal the message hidden in an image, or the file hidden with `stegosaurust encode --embed-metadata`
+#[wasm_bindgen]
+pub fn reveal(image: &[u8], options: &Options) -> Result<Vec<u8>, JsError> {
+    let image = image::load_from_memory(image)?.into_rgb8();
+    match Payload::from_bytes(options.stego()?.reveal(&image)?)? {
+        Payload::Raw(data) | Payload::File { data, .. } => Ok(data),
+        Payload::Archive(_) => Err(JsError::new(
+            "the image hides an archive, extract it with `stegosaurust decode --extract`",
+        )),
+        Payload::Chunk { .. } => Err(JsError::new(
+            "the image hides part of a file, restore it with `stegosaurust disguise --decode`",
+        )),
+    }
+}
+
+/// Length to reveal a message hidden with a `linear` distribution with, from its length once compressed,
+/// encrypted and base64 encoded
+#[wasm_bindgen(js_name = linearLength)]
+pub fn linear_length(encoded_len: usize) -> usize {
+    crate::steganography::linear_length(encoded_len)
+}
+
+/// Number of bytes that can be hidden in a cover image, once compressed, encrypted and base64 encoded
+#[wasm_bindgen]
+pub fn capacity(cover: &[u8]) -> Result<usize, JsError> {
+    let cover = image::load_from_memory(cover)?.into_rgb8();
+    Ok(crate::steganography::max_len(cover.width(), cover.height()))
+}
+
+#[cfg(all(test, target_arch = "wasm32"))]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let cover =
+            image::RgbImage::from_fn(width, height, |x, y| image::Rgb([x as u8, y as u8, 0]));
+        let mut png = Vec::new();
+        cover
+            .write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)
+            .unwrap();
+        png
+    }
+
+    #[wasm_bindgen_test]
+    fn test_hide_reveal() {
+        let cover = png(64, 64);
+        let mut options = Options::new();
+        options.method = Some("rsb".to_string());
+        options.seed = Some("seed".to_string());
+        options.max_bit = Some(2);
+        options.compress = true;
+        options.key = Some("secret".to_string());
+        options.base64 = true;
+        let hidden = hide(&cover, b"hello world", &options).unwrap();
+        assert_eq!(reveal(&hidden.image, &options).unwrap(), b"hello world");
+    }
+
+    #[wasm_bindgen_test]
+    fn test_hide_reveal_linear() {
+        let cover = png(64, 64);
+        let mut options = Options::new();
+        options.distribution = Some("linear".to_string());
+        options.compress = true;
+        options.key = Some("secret".to_string());
+        let hidden = hide(&cover, b"hello world", &options).unwrap();
+        assert_eq!(hidden.linear_length, linear_length(hidden.encoded_len));
+        assert!(reveal(&hidden.image, &options).is_err());
+        options.distribution = Some(format!("linear-{}", hidden.linear_length));
+        assert_eq!(reveal(&hidden.image, &options).unwrap(), b"hello world");
+    }
+
+    #[wasm_bindgen_test]
+    fn test_reveal_file_payload() {
+        let metadata = crate::payload::FileMetadata {
+            name: "notes.txt".to_string(),
+            mode: None,
+            modified: None,
+            content_type: None,
+        };
+        let payload = Payload::File {
+            metadata,
+            data: b"notes".to_vec(),
+        };
+        let options = Options::new();
+        let cover = image::load_from_memory(&png(64, 64)).unwrap().into_rgb8();
+        let image = options
+            .stego()
+            .unwrap()
+            .hide(&cover, &payload.into_bytes())
+            .unwrap();
+        let mut png = Vec::new();
+        image
+            .write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)
+            .unwrap();
+        assert_eq!(reveal(&png, &options).unwrap(), b"notes");
+    }
+
+    #[wasm_bindgen_test]
+    fn test_invalid_options() {
+        let cover = png(64, 64);
+        let mut options = Options::new();
+        options.method = Some("rsb".to_string());
+        assert!(hide(&cover, b"hello", &options).is_err());
+        options.method = Some("msb".to_string());
+        assert!(hide(&cover, b"hello", &options).is_err());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_too_long_and_not_an_image() {
+        let cover = png(8, 8);
+        let message = vec![0; capacity(&cover).unwrap() + 1];
+        assert!(hide(&cover, &message, &Options::new()).is_err());
+        assert!(reveal(b"not an image", &Options::new()).is_err());
+    }
+}
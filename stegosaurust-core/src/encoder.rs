@@ -0,0 +1,360 @@
+use alloc::borrow::ToOwned;
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::vec::Vec;
+use log::debug;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use crate::{BitDistribution, BitEncoding, EmbedError, END};
+
+/// A `BitEncoder` is something that can perform `BitEncoding`
+pub struct BitEncoder {
+    encoder: Box<dyn BitEncoding>,
+    /// Bit distribution method to use when encoding bits
+    bit_dist: BitDistribution,
+    /// Whether or not to add token sequence at the end of encoding
+    end_sequence: bool,
+}
+
+impl BitEncoder {
+    pub fn new(encoder: Box<dyn BitEncoding>, bd: Option<BitDistribution>) -> Self {
+        BitEncoder {
+            encoder,
+            bit_dist: bd.unwrap_or_default(),
+            end_sequence: true,
+        }
+    }
+
+    /// Whether to hide the end sequence after messages and reveal them up to it, rather than filling the buffer
+    pub fn with_end_sequence(mut self, end_sequence: bool) -> Self {
+        self.end_sequence = end_sequence;
+        self
+    }
+
+    /// The bit encoding hiding each bit
+    pub fn encoding(&self) -> &dyn BitEncoding {
+        self.encoder.as_ref()
+    }
+
+    /// The distribution of the bits in the buffer
+    pub fn distribution(&self) -> &BitDistribution {
+        &self.bit_dist
+    }
+
+    /// Whether the end sequence is hidden after messages
+    pub fn end_sequence(&self) -> bool {
+        self.end_sequence
+    }
+}
+
+/// Number of colour values of an image each job hides bits in
+const EMBED_CHUNK_LEN: usize = 1 << 16;
+/// Number of bytes of a message each job reveals
+const EXTRACT_CHUNK_LEN: usize = 1 << 12;
+/// Number of bytes of a message revealed before looking for the end sequence
+const EXTRACT_BLOCK_LEN: usize = 1 << 16;
+
+impl BitEncoder {
+    /// Hides a message in the raw buffer of an RGB image, with three colour values to a pixel
+    pub fn embed(&self, buf: &mut [u8], msg: &[u8]) -> Result<(), EmbedError> {
+        let msg = if self.end_sequence {
+            [msg, END].concat()
+        } else {
+            msg.to_owned()
+        };
+        let bit_len = msg.len() * 8;
+        if bit_len > buf.len() {
+            return Err(EmbedError::Encoding(format!(
+                "{} bits do not fit in {} colour values",
+                bit_len,
+                buf.len()
+            )));
+        }
+
+        // a linear distribution spreads the bits over (number of bits to encode / 3) pixels
+        // because in each pixel we encode 3 bits (rgb)
+        let linspace_length = bit_len.div_ceil(3);
+        let layout = Layout::new(&self.bit_dist, buf.len() / 3, linspace_length);
+        let parallel = !self.encoder.sequential();
+        for_each_chunk_mut(buf, EMBED_CHUNK_LEN, parallel, |start, chunk| {
+            let first = layout.first_bit_from(start).min(bit_len);
+            let last = layout.first_bit_from(start + chunk.len()).min(bit_len);
+            for index in first..last {
+                let bit = (msg[index / 8] >> (7 - index % 8)) & 1;
+                self.encoder
+                    .encode(index, bit, &mut chunk[layout.position(index) - start]);
+            }
+        });
+
+        if let BitDistribution::Linear { length: _ } = self.bit_dist {
+            debug!(
+                "use length '{}' when decoding with linear distribution",
+                linspace_length
+            );
+        }
+        Ok(())
+    }
+
+    /// Reveals a message from the raw buffer of an RGB image, with three colour values to a pixel
+    pub fn extract(&self, buf: &[u8]) -> Result<Vec<u8>, EmbedError> {
+        let length = match self.bit_dist {
+            BitDistribution::Sequential => 0,
+            BitDistribution::Linear { length } => length,
+        };
+        let layout = Layout::new(&self.bit_dist, buf.len() / 3, length);
+        // the last byte may only be partly hidden
+        let (full_len, len) = (layout.bits / 8, layout.bits.div_ceil(8));
+
+        // reveal the message a block at a time, until the block the end sequence is found in
+        let parallel = !self.encoder.sequential();
+        let mut msg = Vec::new();
+        while msg.len() < len {
+            let start = msg.len();
+            msg.resize((start + EXTRACT_BLOCK_LEN).min(len), 0);
+            for_each_chunk_mut(
+                &mut msg[start..],
+                EXTRACT_CHUNK_LEN,
+                parallel,
+                |offset, bytes| {
+                    for (i, byte) in bytes.iter_mut().enumerate() {
+                        let first = (start + offset + i) * 8;
+                        *byte = (first..(first + 8).min(layout.bits)).fold(0, |byte, index| {
+                            (byte << 1) | self.encoder.decode(index, buf[layout.position(index)])
+                        });
+                    }
+                },
+            );
+            if self.end_sequence {
+                // the end sequence may have started in the previous block, and can only follow a whole byte
+                let from = start.saturating_sub(END.len() - 1);
+                let to = msg.len().min(full_len);
+                if let Some(end) = msg[from..to.max(from)]
+                    .windows(END.len())
+                    .position(|bytes| bytes == END)
+                {
+                    msg.truncate(from + end);
+                    return Ok(msg);
+                }
+            }
+        }
+        if self.end_sequence {
+            return Err(EmbedError::EncodingNotFound);
+        }
+        Ok(msg)
+    }
+}
+
+/// Where each bit of a message is hidden in the raw buffer of an image, following a `BitDistribution`
+pub struct Layout {
+    /// Distance between the pixels bits are hidden in with a linear distribution
+    step: Option<f64>,
+    /// Number of bits that can be hidden
+    bits: usize,
+}
+
+impl Layout {
+    /// Lays out bits in the buffer of an image with `pixels` pixels, over `length` of them with a linear distribution
+    pub fn new(dist: &BitDistribution, pixels: usize, length: usize) -> Self {
+        match dist {
+            BitDistribution::Sequential => Layout {
+                step: None,
+                bits: pixels * 3,
+            },
+            BitDistribution::Linear { .. } if pixels == 0 => Layout {
+                step: None,
+                bits: 0,
+            },
+            BitDistribution::Linear { .. } => {
+                // `length` pixels evenly spaced from the first to the last, rounded down
+                let step = if length > 1 {
+                    (pixels - 1) as f64 / (length as f64 - 1.)
+                } else {
+                    0.
+                };
+                Layout {
+                    step: Some(step),
+                    bits: length * 3,
+                }
+            }
+        }
+    }
+
+    /// Number of bits that can be hidden
+    pub fn bits(&self) -> usize {
+        self.bits
+    }
+
+    /// Index of the colour value in the buffer the bit at `index` is hidden in
+    pub fn position(&self, index: usize) -> usize {
+        match self.step {
+            None => index,
+            // truncating rounds down as the position is never negative, without `f64::floor` from std
+            Some(step) => (step * (index / 3) as f64) as usize * 3 + index % 3,
+        }
+    }
+
+    /// Index of the first bit hidden at or after `pos` in the buffer, as the positions of bits only increase
+    fn first_bit_from(&self, pos: usize) -> usize {
+        let (mut low, mut high) = (0, self.bits);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            if self.position(mid) < pos {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        low
+    }
+}
+
+/// Calls `f` with the offset of each chunk of `buf` and the chunk, in order, or in parallel with the `parallel`
+/// feature when `parallel` is set
+fn for_each_chunk_mut<F>(buf: &mut [u8], chunk_len: usize, parallel: bool, f: F)
+where
+    F: Fn(usize, &mut [u8]) + Send + Sync,
+{
+    #[cfg(feature = "parallel")]
+    if parallel {
+        buf.par_chunks_mut(chunk_len)
+            .enumerate()
+            .for_each(|(i, chunk)| f(i * chunk_len, chunk));
+        return;
+    }
+    #[cfg(not(feature = "parallel"))]
+    let _ = parallel;
+    buf.chunks_mut(chunk_len)
+        .enumerate()
+        .for_each(|(i, chunk)| f(i * chunk_len, chunk));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{linear_length, Lsb, Rsb};
+    use alloc::sync::Arc;
+    use alloc::vec;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use itertools_num::linspace;
+
+    #[test]
+    fn test_end_sequence_only_found_on_byte_boundary() {
+        // the bits of the end sequence start half way through the first byte
+        let secret_message = [0x02, 0x45, 0x43, 0x34, 0x70];
+        let enc = BitEncoder::new(Box::<Lsb>::default(), None);
+        let mut buf = vec![0; 32 * 3];
+        enc.embed(&mut buf, &secret_message).unwrap();
+        assert_eq!(enc.extract(&buf).unwrap(), secret_message);
+    }
+
+    #[test]
+    fn test_message_too_long() {
+        let enc = BitEncoder::new(Box::<Lsb>::default(), None);
+        let mut buf = vec![0; 8 * 3];
+        assert!(matches!(
+            enc.embed(&mut buf, b"too long"),
+            Err(EmbedError::Encoding(_))
+        ));
+    }
+
+    #[test]
+    fn test_embed_matches_bit_by_bit() {
+        // enough to be hidden in several chunks and revealed in several blocks
+        let secret_message = (0..EXTRACT_BLOCK_LEN + 100)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<u8>>();
+        let pixels = 500 * 500;
+        let bit_len = (secret_message.len() + END.len()) * 8;
+        for bit_dist in [
+            BitDistribution::Sequential,
+            BitDistribution::Linear {
+                length: linear_length(secret_message.len()),
+            },
+        ] {
+            let enc = BitEncoder::new(
+                Box::new(Rsb::new(3, "seed").unwrap()),
+                Some(bit_dist.clone()),
+            );
+            let mut buf = vec![0; pixels * 3];
+            enc.embed(&mut buf, &secret_message).unwrap();
+
+            // hide each bit in turn at the position of the linear distribution
+            let mut expected = vec![0; pixels * 3];
+            let linear_pixel_dist = linspace(0., (pixels - 1) as f64, bit_len.div_ceil(3))
+                .map(|p: f64| p as usize)
+                .collect::<Vec<usize>>();
+            let rsb = Rsb::new(3, "seed").unwrap();
+            for (index, bit) in secret_message
+                .iter()
+                .chain(END)
+                .flat_map(|byte| (0..8).rev().map(move |i| (byte >> i) & 1))
+                .enumerate()
+            {
+                let pos = match bit_dist {
+                    BitDistribution::Sequential => index,
+                    BitDistribution::Linear { .. } => linear_pixel_dist[index / 3] * 3 + index % 3,
+                };
+                rsb.encode(index, bit, &mut expected[pos]);
+            }
+            assert!(buf == expected);
+            assert!(enc.extract(&buf).unwrap() == secret_message);
+        }
+    }
+
+    /// Lsb, counting the bits encoded and decoded that do not follow the bit before them
+    struct InOrder {
+        next: AtomicUsize,
+        out_of_order: Arc<AtomicUsize>,
+    }
+
+    impl InOrder {
+        fn check(&self, index: usize) {
+            let next = self.next.swap(index + 1, Ordering::SeqCst);
+            if index != 0 && index != next {
+                self.out_of_order.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+    }
+
+    impl BitEncoding for InOrder {
+        fn encode(&self, index: usize, bit: u8, color_val: &mut u8) {
+            self.check(index);
+            Lsb.encode(index, bit, color_val);
+        }
+
+        fn decode(&self, index: usize, color_val: u8) -> u8 {
+            self.check(index);
+            Lsb.decode(index, color_val)
+        }
+
+        fn sequential(&self) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn test_sequential_encoding_in_order() {
+        // enough to be hidden in several chunks and revealed in several blocks
+        let secret_message = vec![0x5a; EXTRACT_BLOCK_LEN + 100];
+        let out_of_order = Arc::new(AtomicUsize::new(0));
+        let encoding = InOrder {
+            next: AtomicUsize::new(0),
+            out_of_order: out_of_order.clone(),
+        };
+        let enc = BitEncoder::new(Box::new(encoding), None).with_end_sequence(false);
+        let mut buf = vec![0; secret_message.len() * 8 + 3];
+        enc.embed(&mut buf, &secret_message).unwrap();
+        assert!(enc.extract(&buf).unwrap().starts_with(&secret_message));
+        assert_eq!(out_of_order.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_without_end_sequence() {
+        let enc = BitEncoder::new(Box::<Lsb>::default(), None).with_end_sequence(false);
+        let mut buf = vec![0; 8 * 3];
+        enc.embed(&mut buf, b"abc").unwrap();
+        assert_eq!(enc.extract(&buf).unwrap(), b"abc");
+        assert!(!enc.end_sequence());
+    }
+}
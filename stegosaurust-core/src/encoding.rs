@@ -0,0 +1,183 @@
+use alloc::format;
+use rand_core::RngCore;
+use rand_pcg::Pcg64;
+use rand_seeder::Seeder;
+
+use crate::EmbedError;
+
+/// Bit masks for setting/clearing bits in bytes.
+#[derive(Clone, Copy)]
+enum BitMask {
+    One = 0b0000_0001,
+    Two = 0b0000_0010,
+    Four = 0b0000_0100,
+    Eight = 0b0000_1000,
+}
+
+/// Masks for each significant bit, from the least significant
+const BIT_MASKS: [BitMask; 4] = [BitMask::One, BitMask::Two, BitMask::Four, BitMask::Eight];
+
+impl TryFrom<u8> for BitMask {
+    type Error = EmbedError;
+    fn try_from(num: u8) -> Result<Self, Self::Error> {
+        match num {
+            1..=4 => Ok(BIT_MASKS[num as usize - 1]),
+            other => Err(EmbedError::InvalidConfig(format!(
+                "max bit must be between 1 and 4, got {}",
+                other
+            ))),
+        }
+    }
+}
+
+/// Behaviour to encode a single bit of information into a byte
+///
+/// Each bit is encoded knowing only its index in the message, so bits can be encoded in any order, or in parallel,
+/// unless the encoding is `sequential`.
+pub trait BitEncoding: Send + Sync {
+    /// Encode the bit at `index` of a message into a byte
+    fn encode(&self, index: usize, bit: u8, color_val: &mut u8);
+    /// Decode the bit at `index` of a message from a byte
+    fn decode(&self, index: usize, color_val: u8) -> u8;
+    /// Whether bits must be encoded and decoded one at a time in order of their index, even with the `parallel` feature
+    fn sequential(&self) -> bool {
+        false
+    }
+}
+
+/// Least significant bit encoding
+///
+/// With a binary message, each bit of the message is encoded
+/// into the least significant bit of each RGB byte of each pixel.
+pub struct Lsb;
+
+impl Lsb {
+    /// Creates an new instance of `Lsb`
+    pub fn new() -> Self {
+        Lsb {}
+    }
+}
+
+impl Default for Lsb {
+    fn default() -> Self {
+        Lsb::new()
+    }
+}
+
+/// Random significant bit encoding
+///
+/// With a binary message, each bit of the message is encoded
+/// randomly into one of the `n` least significant bits of each RGB byte of each pixel.
+#[derive(Clone)]
+pub struct Rsb {
+    /// The maximum significant bit to possibly set/clear when encoding (1-4)
+    max: u8,
+    /// Key derived from the seed, determining which significant bit to encode to/decode from for each bit
+    key: u64,
+}
+
+impl Rsb {
+    /// Creates an new instance of `Rsb`, hiding bits in one of the `max` (1-4) least significant bits
+    pub fn new(max: u8, seed: &str) -> Result<Self, EmbedError> {
+        BitMask::try_from(max)?;
+        let mut rng: Pcg64 = Seeder::from(seed).make_rng();
+        Ok(Rsb {
+            max,
+            key: rng.next_u64(),
+        })
+    }
+
+    /// Randomly choose the `BitMask` for encoding/decoding the bit at `index`.
+    ///
+    /// The choice is the `index`th output of a SplitMix64 generator keyed by the seed,
+    /// so any bit's mask is known without generating those of the bits before it.
+    fn mask_at(&self, index: usize) -> BitMask {
+        let mut z = self
+            .key
+            .wrapping_add((index as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+        // scale the top 32 bits into 0..max
+        let n = ((z >> 32) * self.max as u64) >> 32;
+        BIT_MASKS[n as usize]
+    }
+}
+
+impl BitEncoding for Rsb {
+    fn encode(&self, index: usize, bit: u8, color_val: &mut u8) {
+        let mask = self.mask_at(index) as u8;
+        // set or clear the bit without branching on it, as the bits of a message are unpredictable
+        *color_val = (*color_val & !mask) | (mask * (bit & 1));
+    }
+
+    fn decode(&self, index: usize, color_val: u8) -> u8 {
+        let mask = self.mask_at(index);
+        let c = color_val & mask as u8;
+        u8::from(c > 0)
+    }
+}
+
+impl BitEncoding for Lsb {
+    fn encode(&self, _index: usize, bit: u8, color_val: &mut u8) {
+        *color_val = (*color_val & !(BitMask::One as u8)) | (bit & 1);
+    }
+
+    fn decode(&self, _index: usize, color_val: u8) -> u8 {
+        color_val & BitMask::One as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn test_rsb_random_determined_from_seed() {
+        let rsb1 = Rsb::new(4, "seed").unwrap();
+        let rsb2 = Rsb::new(4, "seed").unwrap();
+        for index in 0..10 {
+            assert_eq!(rsb1.mask_at(index) as u8, rsb2.mask_at(index) as u8);
+        }
+    }
+
+    #[test]
+    fn test_rsb_random_determined_from_seed_different() {
+        let rsb1 = Rsb::new(4, "seed").unwrap();
+        let rsb2 = Rsb::new(4, "seeb").unwrap();
+        let it = 1000;
+        let mut matches = Vec::with_capacity(it);
+        for index in 0..it {
+            matches.push(rsb1.mask_at(index) as u8 == rsb2.mask_at(index) as u8);
+        }
+        assert!(matches.contains(&false));
+    }
+
+    #[test]
+    fn test_rsb_uses_every_bit() {
+        let rsb = Rsb::new(3, "seed").unwrap();
+        let mut counts = [0; 9];
+        for index in 0..3000 {
+            counts[rsb.mask_at(index) as usize] += 1;
+        }
+        assert_eq!(counts.iter().filter(|&&n| n > 900).count(), 3);
+    }
+
+    #[test]
+    fn test_invalid_max_bit() {
+        for max in [0, 5, u8::MAX] {
+            assert!(matches!(
+                BitMask::try_from(max),
+                Err(EmbedError::InvalidConfig(_))
+            ));
+            assert!(matches!(
+                Rsb::new(max, "seed"),
+                Err(EmbedError::InvalidConfig(_))
+            ));
+        }
+        for max in 1..=4 {
+            assert_eq!(BitMask::try_from(max).unwrap() as u8, 1 << (max - 1));
+        }
+    }
+}
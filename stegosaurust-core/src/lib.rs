@@ -0,0 +1,199 @@
+//! The bit embedding engine of [stegosaurust](https://docs.rs/stegosaurust), hiding messages in the raw buffers
+//! of RGB images, with three colour values to a pixel.
+//!
+//! It needs no operating system, only an allocator, so it builds for `no_std` targets such as embedded devices.
+//! Reading and writing images, files and the network are left to `stegosaurust`, which re-exports this crate
+//! in its `steganography` module.
+//!
+//! ```rust
+//! use stegosaurust_core::{BitEncoder, Rsb};
+//!
+//! let encoder = BitEncoder::new(Box::new(Rsb::new(2, "seed").unwrap()), None);
+//! let mut pixels = vec![0; 32 * 32 * 3];
+//! encoder.embed(&mut pixels, b"hello world").unwrap();
+//! assert_eq!(encoder.extract(&pixels).unwrap(), b"hello world");
+//! ```
+#![no_std]
+
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use core::fmt;
+use core::str::FromStr;
+
+/// Bit encoders hiding whole messages in buffers, following a bit distribution.
+mod encoder;
+/// Bit encodings hiding single bits in colour values.
+mod encoding;
+
+pub use encoder::{BitEncoder, Layout};
+pub use encoding::{BitEncoding, Lsb, Rsb};
+
+/// Sequence of bytes marking the end of a hidden message
+pub const END: &[u8] = b"$T3G";
+
+/// Errors hiding or revealing a message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EmbedError {
+    /// No end sequence was found, so no message is hidden in the buffer
+    EncodingNotFound,
+    /// The message could not be hidden, e.g. as it does not fit in the buffer
+    Encoding(String),
+    /// An option was not valid, e.g. a max bit out of range
+    InvalidConfig(String),
+}
+
+impl fmt::Display for EmbedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::EncodingNotFound => write!(f, "Encoded message not found in data"),
+            Self::Encoding(err) => write!(f, "Error encoding message: `{}`", err),
+            Self::InvalidConfig(err) => write!(f, "Invalid configuration: `{}`", err),
+        }
+    }
+}
+
+impl core::error::Error for EmbedError {}
+
+/// Supported steganography encoding algorithms
+#[derive(Debug, Clone, Copy, Default)]
+pub enum StegMethod {
+    #[default]
+    /// Least significant bit encoding
+    ///
+    /// With a binary message, each bit of the message is encoded
+    /// into the least significant bit of each RGB byte of each pixel.
+    LeastSignificantBit,
+    /// Random significant bit encoding
+    ///
+    /// With a binary message, each bit of the message is encoded
+    /// randomly into one of the `n` least significant bits of each RGB byte of each pixel.
+    RandomSignificantBit,
+}
+
+impl FromStr for StegMethod {
+    type Err = EmbedError;
+    fn from_str(method: &str) -> Result<Self, Self::Err> {
+        match method {
+            "lsb" => Ok(Self::LeastSignificantBit),
+            "rsb" => Ok(Self::RandomSignificantBit),
+            other => Err(EmbedError::InvalidConfig(format!(
+                "unknown encoding method: {}",
+                other
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for StegMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::LeastSignificantBit => write!(f, "lsb"),
+            Self::RandomSignificantBit => write!(f, "rsb"),
+        }
+    }
+}
+
+/// Supported bit encoding bit distribution methods
+#[derive(Debug, Clone, Default)]
+pub enum BitDistribution {
+    #[default]
+    /// Encode bits sequentially into the image starting from top-left
+    Sequential,
+    /// Evenly space out the bits in the image so not all packed into top-left
+    Linear { length: usize },
+}
+
+impl FromStr for BitDistribution {
+    type Err = EmbedError;
+    fn from_str(method: &str) -> Result<Self, Self::Err> {
+        match method.split_once('-') {
+            None if method == "sequential" => Ok(Self::Sequential),
+            None if method == "linear" => Ok(Self::Linear { length: 0 }),
+            Some(("linear", length)) => length
+                .parse::<usize>()
+                .map(|length| Self::Linear { length })
+                .map_err(|err| {
+                    EmbedError::InvalidConfig(format!(
+                        "error parsing message length in linear bit distribution: {}",
+                        err
+                    ))
+                }),
+            _ => Err(EmbedError::InvalidConfig(format!(
+                "unknown bit distribution {}",
+                method
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for BitDistribution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Sequential => write!(f, "sequential"),
+            Self::Linear { length: 0 } => write!(f, "linear"),
+            Self::Linear { length } => write!(f, "linear-{}", length),
+        }
+    }
+}
+
+/// Computes the maximum length message that can be encoded with bit encoding into an image of the given dimensions
+pub fn max_len(width: u32, height: u32) -> usize {
+    (width as usize * height as usize * 3).saturating_sub(END.len() * 8) / 8
+}
+
+/// Computes the length to decode a message of `msg_len` bytes hidden with a linear distribution, including the end sequence
+pub fn linear_length(msg_len: usize) -> usize {
+    ((msg_len + END.len()) * 8).div_ceil(3)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_steg_method() {
+        assert!(matches!(
+            "rsb".parse::<StegMethod>(),
+            Ok(StegMethod::RandomSignificantBit)
+        ));
+        assert!(matches!(
+            "msb".parse::<StegMethod>(),
+            Err(EmbedError::InvalidConfig(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_bit_distribution() {
+        assert!(matches!(
+            "sequential".parse::<BitDistribution>(),
+            Ok(BitDistribution::Sequential)
+        ));
+        assert!(matches!(
+            "linear".parse::<BitDistribution>(),
+            Ok(BitDistribution::Linear { length: 0 })
+        ));
+        assert!(matches!(
+            "linear-42".parse::<BitDistribution>(),
+            Ok(BitDistribution::Linear { length: 42 })
+        ));
+        for invalid in [
+            "linear-",
+            "linear-abc",
+            "linear--1",
+            "sequential-1",
+            "random",
+            "",
+        ] {
+            assert!(
+                matches!(
+                    invalid.parse::<BitDistribution>(),
+                    Err(EmbedError::InvalidConfig(_))
+                ),
+                "{}",
+                invalid
+            );
+        }
+    }
+}
@@ -0,0 +1,63 @@
+//! Builds the C library with the `ffi` feature, compiles a C program against it and `include/stegosaurust.h`, and runs it.
+#![cfg(all(feature = "ffi", unix))]
+
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Build the library as a cdylib, as `just ffi` does, returning the directory it is built in.
+///
+/// It is built in a target directory of its own, as cargo holds the lock on the one running this test.
+fn build_library(root: &Path) -> PathBuf {
+    let target_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("ffi");
+    let status = Command::new(env!("CARGO"))
+        .current_dir(root)
+        .args([
+            "rustc",
+            "--lib",
+            "--no-default-features",
+            "--features",
+            "ffi",
+        ])
+        .args(["--crate-type", "cdylib", "--target-dir"])
+        .arg(&target_dir)
+        .status()
+        .expect("failed to run cargo");
+    assert!(status.success(), "failed to build the C library");
+    target_dir.join("debug")
+}
+
+#[test]
+fn test_c_program() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let lib_dir = build_library(root);
+    let program = lib_dir.join("ffi_roundtrip");
+
+    let compiler = env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let status = Command::new(compiler)
+        .arg(root.join("tests/ffi/roundtrip.c"))
+        .arg("-std=c99")
+        .arg("-Wall")
+        .arg("-Werror")
+        .arg("-I")
+        .arg(root.join("include"))
+        .arg("-L")
+        .arg(&lib_dir)
+        .arg("-lstegosaurust")
+        .arg("-o")
+        .arg(&program)
+        .status()
+        .expect("failed to run the C compiler");
+    assert!(status.success(), "failed to compile tests/ffi/roundtrip.c");
+
+    let library_path = if cfg!(target_os = "macos") {
+        "DYLD_LIBRARY_PATH"
+    } else {
+        "LD_LIBRARY_PATH"
+    };
+    let status = Command::new(&program)
+        .env(library_path, &lib_dir)
+        .status()
+        .unwrap();
+    assert!(status.success(), "tests/ffi/roundtrip.c failed");
+}
@@ -0,0 +1,98 @@
+/* Hides a payload in an image buffer and reveals it through the C ABI of stegosaurust. */
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "stegosaurust.h"
+
+#define WIDTH 64
+#define HEIGHT 64
+
+static int failures = 0;
+
+static void expect(StegStatus status, StegStatus expected, const char *what) {
+    if (status != expected) {
+        fprintf(stderr, "%s: got %d (%s), expected %d (%s)\n", what, status, steg_status_message(status),
+                expected, steg_status_message(expected));
+        failures++;
+    }
+}
+
+int main(void) {
+    static uint8_t pixels[WIDTH * HEIGHT * 3];
+    for (size_t i = 0; i < sizeof(pixels); i++) {
+        pixels[i] = (uint8_t)(i * 31);
+    }
+    const char *message = "hello from C";
+    size_t message_len = strlen(message);
+
+    if (steg_capacity(WIDTH, HEIGHT) != WIDTH * HEIGHT * 3 / 8 - 4) {
+        fprintf(stderr, "unexpected capacity %zu\n", steg_capacity(WIDTH, HEIGHT));
+        failures++;
+    }
+
+    StegOptions options = steg_options_default();
+    options.method = STEG_METHOD_RSB;
+    options.seed = "seed";
+    options.max_bit = 2;
+    options.compress = true;
+    options.key = "secret";
+    options.base64 = true;
+    expect(steg_encode(&options, pixels, sizeof(pixels), (const uint8_t *)message, message_len, NULL),
+           STEG_STATUS_OK, "encode");
+
+    uint8_t *payload = NULL;
+    size_t payload_len = 0;
+    expect(steg_decode(&options, pixels, sizeof(pixels), &payload, &payload_len), STEG_STATUS_OK, "decode");
+    if (payload_len != message_len || memcmp(payload, message, message_len) != 0) {
+        fprintf(stderr, "decoded %zu bytes not matching the message\n", payload_len);
+        failures++;
+    }
+    steg_bytes_free(payload, payload_len);
+
+    static uint8_t linear_pixels[WIDTH * HEIGHT * 3];
+    StegOptions linear = steg_options_default();
+    linear.distribution = STEG_DISTRIBUTION_LINEAR;
+    linear.compress = true;
+    linear.key = "secret";
+    size_t encoded_len = 0;
+    expect(steg_encode(&linear, linear_pixels, sizeof(linear_pixels), (const uint8_t *)message, message_len,
+                       &encoded_len),
+           STEG_STATUS_OK, "encode with a linear distribution");
+    linear.linear_length = steg_linear_length(encoded_len);
+    expect(steg_decode(&linear, linear_pixels, sizeof(linear_pixels), &payload, &payload_len), STEG_STATUS_OK,
+           "decode with a linear distribution");
+    if (payload_len != message_len || memcmp(payload, message, message_len) != 0) {
+        fprintf(stderr, "decoded %zu bytes with a linear distribution not matching the message\n", payload_len);
+        failures++;
+    }
+    steg_bytes_free(payload, payload_len);
+
+    if (strcmp(steg_status_message(1000), "unknown status") != 0) {
+        fprintf(stderr, "unexpected message for an unknown status: %s\n", steg_status_message(1000));
+        failures++;
+    }
+
+    options.key = "wrong";
+    expect(steg_decode(&options, pixels, sizeof(pixels), &payload, &payload_len), STEG_STATUS_CRYPTO_DECRYPTION,
+           "decode with the wrong key");
+
+    StegOptions lsb = steg_options_default();
+    expect(steg_decode(&lsb, pixels, sizeof(pixels), &payload, &payload_len), STEG_STATUS_ENCODING_NOT_FOUND,
+           "decode without a message");
+
+    options.max_bit = 7;
+    expect(steg_encode(&options, pixels, sizeof(pixels), (const uint8_t *)message, message_len, NULL),
+           STEG_STATUS_INVALID_CONFIG, "encode with an invalid max bit");
+
+    size_t too_long = steg_capacity(WIDTH, HEIGHT) + 1;
+    uint8_t *long_message = calloc(too_long, 1);
+    expect(steg_encode(&lsb, pixels, sizeof(pixels), long_message, too_long, NULL), STEG_STATUS_ENCODING,
+           "encode a message too long");
+    free(long_message);
+
+    expect(steg_encode(NULL, pixels, sizeof(pixels), NULL, 0, NULL), STEG_STATUS_NULL_POINTER,
+           "encode without options");
+
+    return failures == 0 ? EXIT_SUCCESS : EXIT_FAILURE;
+}